@@ -0,0 +1,222 @@
+//! The `.gitattributes` that mark a file as generated or vendored.
+//!
+//! git2 0.10 only reads the attributes from the working directory and the index, it can't read
+//! them from the tree of a historical commit. So the attribute files are read from the tree of
+//! each analyzed commit and parsed here, with the patterns matched by the gitignore rules.
+
+use git2::{ObjectType, Oid, Repository, Tree};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// The attributes that mark a file as generated or vendored
+#[derive(Clone, Copy)]
+enum Mark {
+    Generated,
+    Vendored,
+    Diff,
+}
+
+/// The state of each mark of a file, None if the attribute is unspecified
+#[derive(Default)]
+struct Marks([Option<bool>; 3]);
+
+impl Marks {
+    /// Whether the file is `linguist-generated`, `linguist-vendored` or `-diff`
+    fn is_generated(&self) -> bool {
+        self.0[Mark::Generated as usize] == Some(true)
+            || self.0[Mark::Vendored as usize] == Some(true)
+            || self.0[Mark::Diff as usize] == Some(false)
+    }
+}
+
+struct Rule {
+    pattern: Gitignore,
+    assignments: Vec<(Mark, Option<bool>)>,
+}
+
+/// The lines of a `.gitattributes` file that assign the marks, see `git help gitattributes`
+struct AttributeFile {
+    dir: PathBuf,
+    rules: Vec<Rule>,
+}
+
+/// Parse an attribute of a line, which is either `name`, `-name`, `!name` or `name=value`
+fn parse_assignment(attr: &str) -> Option<(Mark, Option<bool>)> {
+    let (name, value) = if let Some(name) = attr.strip_prefix('-') {
+        (name, Some(false))
+    } else if let Some(name) = attr.strip_prefix('!') {
+        (name, None)
+    } else if let Some((name, value)) = attr.split_once('=') {
+        (name, Some(value != "false"))
+    } else {
+        (attr, Some(true))
+    };
+    match name {
+        "linguist-generated" => Some((Mark::Generated, value)),
+        "linguist-vendored" => Some((Mark::Vendored, value)),
+        "diff" => Some((Mark::Diff, value)),
+        // The built-in macro attribute, which is `-diff -merge -text`
+        "binary" if value == Some(true) => Some((Mark::Diff, Some(false))),
+        _ => None,
+    }
+}
+
+impl AttributeFile {
+    /// Parse the content of the `.gitattributes` in the directory, which is relative to the root
+    /// of the repository. The lines that don't assign any mark are dropped.
+    fn parse(dir: &Path, content: &str) -> Self {
+        let mut rules = vec![];
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let pattern = match fields.next() {
+                // Negative patterns are forbidden in the attribute files
+                Some(pattern) if !pattern.starts_with('!') => pattern,
+                _ => continue,
+            };
+            let assignments: Vec<_> = fields.filter_map(parse_assignment).collect();
+            if assignments.is_empty() {
+                continue;
+            }
+            let mut builder = GitignoreBuilder::new("");
+            if builder.add_line(None, pattern).is_err() {
+                continue;
+            }
+            if let Ok(pattern) = builder.build() {
+                rules.push(Rule {
+                    pattern,
+                    assignments,
+                });
+            }
+        }
+        Self {
+            dir: dir.to_owned(),
+            rules,
+        }
+    }
+
+    /// Apply the lines matching the path to the marks, the later lines take precedence
+    fn apply(&self, path: &Path, marks: &mut Marks) {
+        let path = match path.strip_prefix(&self.dir) {
+            Ok(path) => path,
+            Err(_) => return,
+        };
+        for rule in self.rules.iter() {
+            if rule.pattern.matched(path, false).is_ignore() {
+                for &(mark, value) in rule.assignments.iter() {
+                    marks.0[mark as usize] = value;
+                }
+            }
+        }
+    }
+}
+
+/// Whether the files are generated in the analyzed commits, the attribute files of each commit
+/// are read from the tree of the commit and each of them is only parsed once
+#[derive(Default)]
+pub(super) struct GeneratedFiles {
+    /// The tree of the last queried commit, and the `.gitattributes` of each directory in it
+    tree: Option<Oid>,
+    blobs: HashMap<PathBuf, Option<Oid>>,
+    files: HashMap<(PathBuf, Oid), AttributeFile>,
+    /// Keyed by the path and the `.gitattributes` of each of its directories
+    generated: HashMap<(PathBuf, Vec<Option<Oid>>), bool>,
+}
+
+impl GeneratedFiles {
+    pub fn is_generated(&mut self, repo: &Repository, tree: &Tree, path: &Path) -> bool {
+        if self.tree != Some(tree.id()) {
+            self.tree = Some(tree.id());
+            self.blobs.clear();
+        }
+
+        let dirs: Vec<_> = path.ancestors().skip(1).collect();
+        let blobs: Vec<_> = dirs
+            .iter()
+            .rev()
+            .map(|&dir| {
+                *self.blobs.entry(dir.to_owned()).or_insert_with(|| {
+                    tree.get_path(&dir.join(".gitattributes"))
+                        .ok()
+                        .filter(|entry| entry.kind() == Some(ObjectType::Blob))
+                        .map(|entry| entry.id())
+                })
+            })
+            .collect();
+
+        let key = (path.to_owned(), blobs);
+        if let Some(&generated) = self.generated.get(&key) {
+            return generated;
+        }
+        let mut marks = Marks::default();
+        for (&dir, blob) in dirs.iter().rev().zip(key.1.iter()) {
+            if let Some(blob) = *blob {
+                let file = self
+                    .files
+                    .entry((dir.to_owned(), blob))
+                    .or_insert_with(|| {
+                        let content = repo.find_blob(blob).map_or_else(
+                            |_| String::new(),
+                            |blob| String::from_utf8_lossy(blob.content()).into_owned(),
+                        );
+                        AttributeFile::parse(dir, &content)
+                    });
+                file.apply(path, &mut marks);
+            }
+        }
+        let generated = marks.is_generated();
+        self.generated.insert(key, generated);
+        generated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_generated(files: &[&AttributeFile], path: &str) -> bool {
+        let mut marks = Marks::default();
+        for file in files {
+            file.apply(Path::new(path), &mut marks);
+        }
+        marks.is_generated()
+    }
+
+    #[test]
+    fn later_lines_take_precedence() {
+        let root = AttributeFile::parse(
+            Path::new(""),
+            "# generated code\n\n*.pb.rs linguist-generated\nsrc/keep.pb.rs -linguist-generated\n",
+        );
+        assert!(is_generated(&[&root], "proto/a.pb.rs"));
+        assert!(is_generated(&[&root], "src/a.pb.rs"));
+        assert!(!is_generated(&[&root], "src/keep.pb.rs"));
+        assert!(!is_generated(&[&root], "src/main.rs"));
+    }
+
+    #[test]
+    fn attribute_forms() {
+        let root = AttributeFile::parse(
+            Path::new(""),
+            "a linguist-vendored=true\nb linguist-vendored=false\nc binary\nd diff=rust\n!e -diff\n",
+        );
+        assert!(is_generated(&[&root], "a"));
+        assert!(!is_generated(&[&root], "b"));
+        assert!(is_generated(&[&root], "c"));
+        assert!(!is_generated(&[&root], "d"));
+        assert!(!is_generated(&[&root], "e"));
+    }
+
+    #[test]
+    fn deeper_files_override_and_are_scoped() {
+        let root = AttributeFile::parse(Path::new(""), "*.rs -diff\n");
+        let vendor = AttributeFile::parse(Path::new("vendor"), "/lib.rs !diff\n");
+        assert!(!is_generated(&[&root, &vendor], "vendor/lib.rs"));
+        assert!(is_generated(&[&root, &vendor], "vendor/src/lib.rs"));
+        assert!(is_generated(&[&root, &vendor], "lib.rs"));
+    }
+}
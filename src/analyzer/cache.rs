@@ -0,0 +1,199 @@
+use super::repo::AuthorEntry;
+use super::{ConstatError, GitCommit, GitRepo, Tree};
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::{Error as IoError, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// The on-disk cache of the analyzed trees, keyed by the commit id.
+///
+/// Besides the tree, each entry carries a payload owned by the caller, which is typically the
+/// statistics computed from the history before the cached commit, so that a re-run only needs to
+/// analyze the commits that are newer than the cached ones.
+pub struct StatCache {
+    dir: PathBuf,
+    key: String,
+}
+
+/// Bumped whenever the layout of the cached trees or payloads changes, so that stale entries are
+/// not read
+const CACHE_VERSION: u32 = 3;
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry<'a> {
+    authors: Vec<AuthorEntry>,
+    tree: Tree<'a>,
+}
+
+fn bad_cache<E: std::error::Error + Send + Sync + 'static>(path: &Path, e: E) -> ConstatError {
+    ConstatError::Io(path.to_owned(), IoError::new(ErrorKind::InvalidData, e))
+}
+
+fn write_file(path: &Path, data: &[u8]) -> Result<(), ConstatError> {
+    let temp_path = path.with_extension("tmp");
+    std::fs::write(&temp_path, data)
+        .and_then(|_| std::fs::rename(&temp_path, path))
+        .map_err(|e| ConstatError::Io(path.to_owned(), e))
+}
+
+impl StatCache {
+    /// Create the cache in the directory. Entries are only shared between the runs with the
+    /// same key, thus the key should cover everything that affects the payload.
+    pub fn new<P: AsRef<Path>>(dir: P, key: &str) -> Self {
+        Self {
+            dir: dir.as_ref().to_owned(),
+            key: key.to_string(),
+        }
+    }
+
+    fn entry_path(&self, repo: &GitRepo, commit: &GitCommit, ext: &str) -> Option<PathBuf> {
+        let mut hasher = DefaultHasher::new();
+        CACHE_VERSION.hash(&mut hasher);
+        self.key.hash(&mut hasher);
+        repo.identity_digest().hash(&mut hasher);
+        let id = commit.id()?;
+        Some(
+            self.dir
+                .join(format!("{:016x}", hasher.finish()))
+                .join(format!("{}.{}", id, ext)),
+        )
+    }
+
+    /// Check if the tree of the commit is cached
+    pub fn contains(&self, repo: &GitRepo, commit: &GitCommit) -> bool {
+        self.entry_path(repo, commit, "tree")
+            .map_or(false, |path| path.is_file())
+    }
+
+    /// Load the cached tree of the commit
+    pub fn load_tree<'a>(
+        &self,
+        repo: &GitRepo,
+        commit: &GitCommit,
+    ) -> Result<Option<Tree<'a>>, ConstatError> {
+        let path = match self.entry_path(repo, commit, "tree") {
+            Some(path) if path.is_file() => path,
+            _ => return Ok(None),
+        };
+        let data = std::fs::read(&path).map_err(|e| ConstatError::Io(path.clone(), e))?;
+        let entry: CacheEntry = bincode::deserialize(&data).map_err(|e| bad_cache(&path, e))?;
+        let mut tree = entry.tree;
+        tree.remap_authors(&repo.import_authors(&entry.authors));
+        Ok(Some(tree))
+    }
+
+    /// Load the payload stored along with the tree of the commit
+    pub fn load_payload(
+        &self,
+        repo: &GitRepo,
+        commit: &GitCommit,
+    ) -> Result<Option<Vec<u8>>, ConstatError> {
+        match self.entry_path(repo, commit, "payload") {
+            Some(path) if path.is_file() => std::fs::read(&path)
+                .map(Some)
+                .map_err(|e| ConstatError::Io(path, e)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Drop the cached tree and payload of the commit, for example when the payload is corrupt
+    pub fn remove(&self, repo: &GitRepo, commit: &GitCommit) -> Result<(), ConstatError> {
+        // The tree is removed first, so that a half removed entry isn't visible
+        for ext in ["tree", "payload"].iter() {
+            if let Some(path) = self.entry_path(repo, commit, ext) {
+                match std::fs::remove_file(&path) {
+                    Err(e) if e.kind() != ErrorKind::NotFound => {
+                        return Err(ConstatError::Io(path, e));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Store the tree of the commit and the payload
+    pub fn store(
+        &self,
+        repo: &GitRepo,
+        commit: &GitCommit,
+        tree: &Tree,
+        payload: &[u8],
+    ) -> Result<(), ConstatError> {
+        let (tree_path, payload_path) = match (
+            self.entry_path(repo, commit, "tree"),
+            self.entry_path(repo, commit, "payload"),
+        ) {
+            (Some(tree_path), Some(payload_path)) => (tree_path, payload_path),
+            _ => return Ok(()),
+        };
+
+        if let Some(dir) = tree_path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| ConstatError::Io(dir.to_owned(), e))?;
+        }
+
+        let entry = CacheEntry {
+            authors: repo.export_authors(),
+            tree: tree.clone(),
+        };
+        let data = bincode::serialize(&entry).map_err(|e| bad_cache(&tree_path, e))?;
+
+        // The tree is written last, so the entry is visible only when it's complete
+        write_file(&payload_path, payload)?;
+        write_file(&tree_path, &data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::VersionSpec;
+    use super::*;
+
+    #[test]
+    fn entries_round_trip_within_the_same_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let git = git2::Repository::init(dir.path().join("repo")).unwrap();
+        std::fs::write(dir.path().join("repo/main.rs"), "fn main() {\n}\n").unwrap();
+        let mut index = git.index().unwrap();
+        index.add_path(Path::new("main.rs")).unwrap();
+        let git_tree = git.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("Jane Doe", "jane@example.com").unwrap();
+        git.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "Initial",
+            &git_tree,
+            &[],
+        )
+        .unwrap();
+
+        let repo = GitRepo::open(dir.path().join("repo")).unwrap();
+        let commit = repo.find_commit(VersionSpec::Head).unwrap();
+        let author = repo.query_identity_id("Jane Doe", "jane@example.com");
+        let tree = Tree::from_commit(&commit, author, false).unwrap();
+
+        let cache = StatCache::new(dir.path().join("cache"), "key");
+        assert!(!cache.contains(&repo, &commit));
+        assert_eq!(cache.load_payload(&repo, &commit).unwrap(), None);
+        cache.store(&repo, &commit, &tree, b"payload").unwrap();
+        assert!(cache.contains(&repo, &commit));
+        assert_eq!(
+            cache.load_payload(&repo, &commit).unwrap(),
+            Some(b"payload".to_vec())
+        );
+
+        // The author ids are remapped when the tree is loaded by another run
+        let rerun = GitRepo::open(dir.path().join("repo")).unwrap();
+        rerun.query_identity_id("John Roe", "john@example.com");
+        let loaded = cache.load_tree(&rerun, &commit).unwrap().unwrap();
+        assert_eq!(loaded.stat(|_| true, &rerun), vec![(0, 0), (1, 2)]);
+
+        let other = StatCache::new(dir.path().join("cache"), "other key");
+        assert!(!other.contains(&repo, &commit));
+        assert!(other.load_tree(&repo, &commit).unwrap().is_none());
+    }
+}
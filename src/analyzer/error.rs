@@ -0,0 +1,43 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::path::PathBuf;
+
+/// The error raised by the analyzer
+#[derive(Debug)]
+pub enum ConstatError {
+    /// The path doesn't point to a readable git repository
+    RepoNotFound(PathBuf, git2::Error),
+    /// The revision can not be resolved to a commit
+    BadRevision(String, git2::Error),
+    /// Failed to compute the diff between two commits
+    DiffFailure(git2::Error),
+    /// The object database contains an object we can not read
+    CorruptObject(git2::Error),
+    /// Failed to read or write a file used by the analyzer
+    Io(PathBuf, std::io::Error),
+}
+
+impl Display for ConstatError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::RepoNotFound(path, e) => {
+                write!(f, "Cannot open repository {}: {}", path.display(), e)
+            }
+            Self::BadRevision(rev, e) => write!(f, "Cannot resolve revision {}: {}", rev, e),
+            Self::DiffFailure(e) => write!(f, "Cannot compute diff: {}", e),
+            Self::CorruptObject(e) => write!(f, "Corrupt object: {}", e),
+            Self::Io(path, e) => write!(f, "Cannot access {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl std::error::Error for ConstatError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::RepoNotFound(_, e)
+            | Self::BadRevision(_, e)
+            | Self::DiffFailure(e)
+            | Self::CorruptObject(e) => Some(e),
+            Self::Io(_, e) => Some(e),
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+/// A name and email pair, either of them can be omitted in a mailmap entry
+type Identity = (Option<String>, Option<String>);
+
+/// The identity mapping described by a mailmap file, see `git help mailmap` for details
+#[derive(Default)]
+pub struct Mailmap {
+    entries: HashMap<(String, Option<String>), Identity>,
+    digest: u64,
+}
+
+/// Split the leading `Name <email>` out of the text, returns the name, email and the remaining text
+pub(super) fn split_identity(text: &str) -> Option<(Option<String>, String, &str)> {
+    let begin = text.find('<')?;
+    let end = begin + text[begin..].find('>')?;
+    let name = text[..begin].trim();
+    let name = if name.is_empty() {
+        None
+    } else {
+        Some(name.to_string())
+    };
+    Some((name, text[begin + 1..end].trim().to_string(), &text[end + 1..]))
+}
+
+impl Mailmap {
+    /// Parse the mailmap content and add the entries to this mailmap.
+    /// Entries added later take precedence over the existing ones.
+    pub fn parse(&mut self, content: &str) {
+        let mut hasher = DefaultHasher::new();
+        self.digest.hash(&mut hasher);
+        content.hash(&mut hasher);
+        self.digest = hasher.finish();
+
+        for line in content.lines() {
+            let line = line.trim();
+            if line.starts_with('#') {
+                continue;
+            }
+            if let Some((proper_name, proper_email, rest)) = split_identity(line) {
+                let proper_email = if proper_email.is_empty() {
+                    None
+                } else {
+                    Some(proper_email)
+                };
+                if let Some((commit_name, commit_email, _)) = split_identity(rest) {
+                    self.entries.insert(
+                        (
+                            commit_email.to_lowercase(),
+                            commit_name.map(|n| n.to_lowercase()),
+                        ),
+                        (proper_name, proper_email),
+                    );
+                } else if let Some(commit_email) = proper_email {
+                    self.entries
+                        .insert((commit_email.to_lowercase(), None), (proper_name, None));
+                }
+            }
+        }
+    }
+
+    /// The digest of all the content parsed into this mailmap
+    pub fn digest(&self) -> u64 {
+        self.digest
+    }
+
+    /// Resolve the identity appears in a commit to the canonical name and email
+    pub fn resolve(&self, name: &str, email: &str) -> (String, String) {
+        let email_key = email.to_lowercase();
+        let entry = self
+            .entries
+            .get(&(email_key.clone(), Some(name.to_lowercase())))
+            .or_else(|| self.entries.get(&(email_key, None)));
+
+        if let Some((proper_name, proper_email)) = entry {
+            (
+                proper_name.as_deref().unwrap_or(name).to_string(),
+                proper_email.as_deref().unwrap_or(email).to_string(),
+            )
+        } else {
+            (name.to_string(), email.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mailmap(content: &str) -> Mailmap {
+        let mut mailmap = Mailmap::default();
+        mailmap.parse(content);
+        mailmap
+    }
+
+    fn identity(name: &str, email: &str) -> (String, String) {
+        (name.to_string(), email.to_string())
+    }
+
+    #[test]
+    fn proper_name_by_email() {
+        let mailmap = mailmap("Jane Doe <jane@example.com>\n");
+        assert_eq!(
+            mailmap.resolve("jdoe", "jane@example.com"),
+            identity("Jane Doe", "jane@example.com")
+        );
+        assert_eq!(
+            mailmap.resolve("jdoe", "JANE@example.com"),
+            identity("Jane Doe", "JANE@example.com")
+        );
+    }
+
+    #[test]
+    fn proper_email_by_email() {
+        let mailmap = mailmap("<jane@example.com> <jane@old.com>\n");
+        assert_eq!(
+            mailmap.resolve("jdoe", "jane@old.com"),
+            identity("jdoe", "jane@example.com")
+        );
+    }
+
+    #[test]
+    fn proper_identity_by_email() {
+        let mailmap = mailmap("Jane Doe <jane@example.com> <jane@old.com>\n");
+        assert_eq!(
+            mailmap.resolve("jdoe", "jane@old.com"),
+            identity("Jane Doe", "jane@example.com")
+        );
+    }
+
+    #[test]
+    fn proper_identity_by_name_and_email() {
+        let mailmap = mailmap("Jane Doe <jane@example.com> jdoe <shared@example.com>\n");
+        assert_eq!(
+            mailmap.resolve("JDoe", "shared@example.com"),
+            identity("Jane Doe", "jane@example.com")
+        );
+        // Only the commit name given in the entry is mapped
+        assert_eq!(
+            mailmap.resolve("john", "shared@example.com"),
+            identity("john", "shared@example.com")
+        );
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_skipped() {
+        let mailmap = mailmap(
+            "# Jane Doe <jane@example.com>\n\n   \nJohn Roe <john@example.com> # the maintainer\n",
+        );
+        assert_eq!(
+            mailmap.resolve("jdoe", "jane@example.com"),
+            identity("jdoe", "jane@example.com")
+        );
+        assert_eq!(
+            mailmap.resolve("jroe", "john@example.com"),
+            identity("John Roe", "john@example.com")
+        );
+        assert_eq!(
+            mailmap.resolve("nobody", "nobody@example.com"),
+            identity("nobody", "nobody@example.com")
+        );
+    }
+
+    #[test]
+    fn later_entries_take_precedence() {
+        let mut mailmap = mailmap("Jane Doe <jane@example.com>\n");
+        let digest = mailmap.digest();
+        mailmap.parse("Jane D. <jane@example.com>\n");
+        assert_ne!(mailmap.digest(), digest);
+        assert_eq!(
+            mailmap.resolve("jdoe", "jane@example.com"),
+            identity("Jane D.", "jane@example.com")
+        );
+    }
+}
@@ -1,32 +1,160 @@
+mod attributes;
+mod cache;
+mod error;
+mod mailmap;
 mod patch;
 mod repo;
 mod tree;
 
-use std::path::Path;
+use chrono::{DateTime, Utc};
+use git2::Oid;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 
-pub use repo::{GitCommit, GitRepo};
+pub use cache::StatCache;
+pub use error::ConstatError;
+pub use repo::{CoAuthorCredit, GitCommit, GitRepo, HistoryGraph, HistoryNode, VersionSpec};
 pub use tree::Tree;
 
+/// The commits to run stat on, the head is always included
+pub enum Samples {
+    /// The commits with the given ids
+    Commits(HashSet<Oid>),
+    /// The last commit on the first-parent chain of the head, by the commit time, not later than
+    /// each of the given times
+    Dates(Vec<DateTime<Utc>>),
+}
+
+impl Samples {
+    /// Find the index of the sampled commits in the history
+    fn select(&self, history: &HistoryGraph) -> HashSet<usize> {
+        let mut ret: HashSet<_> = match self {
+            Samples::Commits(ids) => (0..history.len())
+                .filter(|&idx| {
+                    history
+                        .get_commit(idx)
+                        .and_then(|commit| commit.id())
+                        .map_or(false, |id| ids.contains(&id))
+                })
+                .collect(),
+            Samples::Dates(dates) => {
+                // The commits merged from the side branches aren't sampled, since their times
+                // don't follow the order of the history
+                let chain: HashSet<_> = history
+                    .len()
+                    .checked_sub(1)
+                    .and_then(|head| history.get_commit(head))
+                    .map_or_else(Vec::new, |head| head.first_parent_chain())
+                    .iter()
+                    .filter_map(GitCommit::id)
+                    .collect();
+                let mut times: Vec<_> = (0..history.len())
+                    .filter_map(|idx| {
+                        let commit = history.get_commit(idx)?;
+                        if !chain.contains(&commit.id()?) {
+                            return None;
+                        }
+                        Some((commit.get_timestamp()?, idx))
+                    })
+                    .collect();
+                times.sort();
+                dates
+                    .iter()
+                    .filter_map(|date| {
+                        let end = times.partition_point(|(time, _)| time <= date);
+                        end.checked_sub(1).map(|last| times[last].1)
+                    })
+                    .collect()
+            }
+        };
+        if !history.is_empty() {
+            ret.insert(history.len() - 1);
+        }
+        ret
+    }
+}
+
+/// The options that controls how the history is analyzed
+#[derive(Default)]
+pub struct StatOptions {
+    /// Show detailed computation steps
+    pub verbose: bool,
+    /// An additional mailmap file used to resolve the author identities
+    pub mailmap: Option<PathBuf>,
+    /// The canonical names of the identities, either an email or `Name <email>`, which take
+    /// precedence over the mailmap files
+    pub aliases: Vec<(String, String)>,
+    /// How the lines are credited to the co-authors of a commit
+    pub co_author_credit: CoAuthorCredit,
+    /// The revision to analyze, HEAD by default. A range like `v1.0..v2.0` only analyzes the
+    /// commits in the range and treats the code before the range as older code
+    pub revision: Option<String>,
+    /// Only analyze the history until this time
+    pub until: Option<DateTime<Utc>>,
+    /// The cache of analyzed trees. The history before a cached commit is not analyzed again
+    pub cache: Option<StatCache>,
+    /// Only run stat on the sampled commits, all the commits are still analyzed. The step and
+    /// total passed to stat count the sampled commits only. The commits loaded from the cache are
+    /// always passed to stat, since the payloads of them carry the history before them.
+    pub samples: Option<Samples>,
+}
+
 pub fn run_stat<P,F,S>(
     path: P,
-    verbose: bool,
+    options: &StatOptions,
     commit_filter: F,
     mut stat: S,
-) 
+) -> Result<(), ConstatError>
 where 
     P: AsRef<Path>,
     F: Fn(&GitCommit) -> bool,
     S: FnMut(&GitRepo, &GitCommit, &Tree, usize, usize),
 {
-    let repo = GitRepo::open(path).unwrap();
+    let verbose = options.verbose;
 
-    let commit = repo.find_commit(repo::VersionSpec::Head).unwrap();
+    let mut repo = GitRepo::open(path)?;
+
+    if let Some(mailmap) = options.mailmap.as_ref() {
+        repo.load_mailmap(mailmap)?;
+    }
+
+    for (name, identity) in options.aliases.iter() {
+        repo.add_alias(name, identity);
+    }
+
+    repo.set_co_author_credit(options.co_author_credit);
+
+    let (commit, base) = match options.revision.as_deref() {
+        Some(spec) => repo.find_range(spec)?,
+        None => (repo.find_commit(VersionSpec::Head)?, None),
+    };
+
+    let commit = match options.until {
+        Some(until) => commit.last_before(until),
+        None => commit,
+    };
+
+    let range = match base {
+        Some(base) => Some(repo.range_commits(&base, &commit)?),
+        None => None,
+    };
+
+    let commit_filter = |commit: &GitCommit| {
+        commit_filter(commit)
+            && options
+                .cache
+                .as_ref()
+                .map_or(true, |cache| !cache.contains(&repo, commit))
+            && range
+                .as_ref()
+                .map_or(true, |range| commit.id().map_or(false, |id| range.contains(&id)))
+    };
 
     if verbose { 
         eprintln!("Sorting commits (head = {})", commit.id().unwrap_or(git2::Oid::zero()));
     }
 
-    let result = commit.topological_sort(&commit_filter).unwrap();
+    let result = commit.topological_sort(&commit_filter)?;
     
     if verbose { 
         eprintln!("Found {} commits to process", result.len());
@@ -34,13 +162,28 @@ where
 
     let plan = result.plan();
 
+    let sampled = options.samples.as_ref().map(|samples| {
+        let mut sampled = samples.select(&result);
+        if let Some(cache) = options.cache.as_ref() {
+            sampled.extend((0..result.len()).filter(|&idx| {
+                result.get_parent_idx(idx).map_or(false, |parents| parents.is_empty())
+                    && result
+                        .get_commit(idx)
+                        .map_or(false, |commit| cache.contains(&repo, &commit))
+            }));
+        }
+        sampled
+    });
+    let total = sampled.as_ref().map_or(plan.len(), HashSet::len);
+    let mut stat_step = 0;
+
     let mut trees = std::collections::BTreeMap::new();
     let mut base_line_tree = None;
 
-    for i in 0..plan.len() {
-        let step = &plan[i];
+    for step in plan.iter() {
 
         let commit = result.get_commit(step.processing).unwrap();
+        let time = commit.get_timestamp().map_or(0, |ts| ts.timestamp());
 
         let parents: Vec<_> = result
             .get_parent_idx(step.processing)
@@ -49,39 +192,52 @@ where
             .map(|pid| &trees[pid])
             .collect();
 
-        let tree = if parents.len() == 0 {
+        let cached_tree = match (parents.len(), options.cache.as_ref()) {
+            (0, Some(cache)) => cache.load_tree(&repo, &commit)?,
+            _ => None,
+        };
+
+        let tree = if let Some(tree) = cached_tree {
+            if verbose {
+                eprintln!("Loaded cached commit {}", commit.id().unwrap_or(git2::Oid::zero()));
+            }
+            tree
+        } else if parents.len() == 0 {
             if commit.is_initial_commit() {
                 let empty = tree::Tree::empty();
                 let parent_commits = result.get_parent_commits(step.processing);
-                let patch = commit.diff_with(parent_commits.iter(), verbose).unwrap();
+                let patch = commit.diff_with(parent_commits.iter(), verbose)?;
                 if verbose {
                     eprintln!("Analyzing initial commit {}", commit.id().unwrap_or(git2::Oid::zero()));
                 }
-                tree::Tree::analyze_patch(&[&empty], patch.as_ref(), commit.author_id())
+                tree::Tree::analyze_patch(&[&empty], patch.as_ref(), commit.author_id(), time)
             } else {
                 if verbose {
                     eprintln!("Analyzing boundary commit {}", commit.id().unwrap_or(git2::Oid::zero()));
                 }
                 if base_line_tree.is_none() {
-                    let tree = tree::Tree::from_commit(&commit, repo.query_author_id("Older Code"), verbose);
+                    let tree = tree::Tree::from_commit(&commit, repo.query_author_id("Older Code"), verbose)?;
                     base_line_tree = Some((commit.clone(), tree.clone()));
                     tree
                 } else {
                     let (bc, bt)  = base_line_tree.as_ref().unwrap();
-                    let patch = commit.diff_with([bc.clone()].iter(), verbose).unwrap();
-                    tree::Tree::analyze_patch(&[bt], patch.as_ref(), repo.query_author_id("Older Code"))
+                    let patch = commit.diff_with([bc.clone()].iter(), verbose)?;
+                    tree::Tree::analyze_patch(&[bt], patch.as_ref(), repo.query_author_id("Older Code"), time)
                 }
             }
         } else {
             let parent_commits = result.get_parent_commits(step.processing);
-            let patch = commit.diff_with(parent_commits.iter(),verbose).unwrap();
+            let patch = commit.diff_with(parent_commits.iter(),verbose)?;
             if verbose {
                 eprintln!("Analyzing commit {} (merge from {} parents)", result.get_commit(step.processing).unwrap().id().unwrap_or(git2::Oid::zero()), parent_commits.len());
             }
-            tree::Tree::analyze_patch(parents.as_ref(), patch.as_ref(), commit.author_id())
+            tree::Tree::analyze_patch(parents.as_ref(), patch.as_ref(), commit.author_id(), time)
         };
 
-        stat(&repo, &commit, &tree, i, plan.len());
+        if sampled.as_ref().map_or(true, |sampled| sampled.contains(&step.processing)) {
+            stat(&repo, &commit, &tree, stat_step, total);
+            stat_step += 1;
+        }
 
         trees.insert(step.processing, tree);
 
@@ -89,4 +245,124 @@ where
             trees.remove(remove_idx);
         }
     }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn samples_select_the_last_commit_of_each_date() {
+        let dir = tempfile::tempdir().unwrap();
+        let git = git2::Repository::init(dir.path()).unwrap();
+        let git_tree = git
+            .find_tree(git.index().unwrap().write_tree().unwrap())
+            .unwrap();
+        let mut ids = vec![];
+        // The commits of an author in the same day are analyzed as one, so they are days apart
+        for &time in [864_000, 1_728_000, 2_592_000].iter() {
+            let signature =
+                git2::Signature::new("Jane Doe", "jane@example.com", &git2::Time::new(time, 0))
+                    .unwrap();
+            let parent = ids.last().map(|&id| git.find_commit(id).unwrap());
+            let parents: Vec<_> = parent.iter().collect();
+            let id = git
+                .commit(
+                    Some("HEAD"),
+                    &signature,
+                    &signature,
+                    "Commit",
+                    &git_tree,
+                    &parents,
+                )
+                .unwrap();
+            ids.push(id);
+        }
+
+        let repo = GitRepo::open(dir.path()).unwrap();
+        let history = repo
+            .find_commit(VersionSpec::Head)
+            .unwrap()
+            .topological_sort(|_: &GitCommit| true)
+            .unwrap();
+        let selected_ids = |samples: Samples| {
+            let mut selected: Vec<_> = samples
+                .select(&history)
+                .into_iter()
+                .map(|idx| history.get_commit(idx).unwrap().id().unwrap())
+                .collect();
+            selected.sort_by_key(|id| ids.iter().position(|x| x == id));
+            selected
+        };
+
+        let date = |time| Utc.timestamp_opt(time, 0).unwrap();
+        let dates = vec![date(500_000), date(864_000), date(1_000_000)];
+        assert_eq!(selected_ids(Samples::Dates(dates)), vec![ids[0], ids[2]]);
+        assert_eq!(
+            selected_ids(Samples::Commits(vec![ids[1]].into_iter().collect())),
+            vec![ids[1], ids[2]]
+        );
+    }
+
+    #[test]
+    fn dates_only_sample_the_first_parent_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        let git = git2::Repository::init(dir.path()).unwrap();
+        let git_tree = git
+            .find_tree(git.index().unwrap().write_tree().unwrap())
+            .unwrap();
+        let day = |day: i64| {
+            let time = git2::Time::new(day * 86_400, 0);
+            git2::Signature::new("Jane Doe", "jane@example.com", &time).unwrap()
+        };
+        // The side branch is committed between the first two commits of the main branch and
+        // merged at the end
+        let first = git
+            .commit(Some("HEAD"), &day(10), &day(10), "First", &git_tree, &[])
+            .unwrap();
+        let first = git.find_commit(first).unwrap();
+        let side = git
+            .commit(None, &day(20), &day(20), "Side", &git_tree, &[&first])
+            .unwrap();
+        let side = git.find_commit(side).unwrap();
+        let second = git
+            .commit(
+                Some("HEAD"),
+                &day(30),
+                &day(30),
+                "Second",
+                &git_tree,
+                &[&first],
+            )
+            .unwrap();
+        let second = git.find_commit(second).unwrap();
+        let merge = git
+            .commit(
+                Some("HEAD"),
+                &day(40),
+                &day(40),
+                "Merge",
+                &git_tree,
+                &[&second, &side],
+            )
+            .unwrap();
+
+        let repo = GitRepo::open(dir.path()).unwrap();
+        let history = repo
+            .find_commit(VersionSpec::Head)
+            .unwrap()
+            .topological_sort(|_: &GitCommit| true)
+            .unwrap();
+        assert_eq!(history.len(), 4);
+        let dates = vec![Utc.timestamp_opt(25 * 86_400, 0).unwrap()];
+        let selected: HashSet<_> = Samples::Dates(dates)
+            .select(&history)
+            .into_iter()
+            .map(|idx| history.get_commit(idx).unwrap().id().unwrap())
+            .collect();
+        assert_eq!(selected, vec![first.id(), merge].into_iter().collect());
+    }
 }
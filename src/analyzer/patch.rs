@@ -62,6 +62,39 @@ impl FilePatch {
             self.patch.push(diff);
         }
     }
+
+    /// The deleted lines that are replaced by the lines inserted at the same place, sorted by
+    /// the line number. A run of deleted lines that isn't followed by an insertion is a pure
+    /// deletion and isn't in the list.
+    pub fn rewritten_lines(&self) -> Vec<u32> {
+        let mut ret = vec![];
+        // The number of lines inserted minus the lines deleted before the current position
+        let mut offset = 0i64;
+        let mut idx = 0;
+        while idx < self.patch.len() {
+            let first = match self.patch[idx] {
+                LinePatch::Insert(_) => {
+                    offset += 1;
+                    idx += 1;
+                    continue;
+                }
+                LinePatch::Delete(line) => line,
+            };
+            let start = idx;
+            while matches!(self.patch.get(idx), Some(LinePatch::Delete(line)) if *line == first + (idx - start) as u32)
+            {
+                idx += 1;
+            }
+            let count = (idx - start) as u32;
+            // The lines inserted in place of the run start where the run starts in the new file
+            if matches!(self.patch.get(idx), Some(LinePatch::Insert(line)) if *line as i64 == first as i64 + offset)
+            {
+                ret.extend(first..first + count);
+            }
+            offset -= count as i64;
+        }
+        ret
+    }
 }
 pub struct TreePatch {
     pub new_author: u32,
@@ -95,3 +128,49 @@ impl TreePatch {
             .sort_by(|a, b| Ord::cmp(&a.new_path(), &b.new_path()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file_patch(patch: Vec<LinePatch>) -> FilePatch {
+        FilePatch {
+            old_path: None,
+            new_path: None,
+            patch,
+        }
+    }
+
+    #[test]
+    fn rewritten_lines_skip_pure_deletions() {
+        // Line 3 is deleted, line 7 is replaced by two lines, and line 15 is replaced by one
+        let patch = file_patch(vec![
+            LinePatch::Delete(3),
+            LinePatch::Delete(7),
+            LinePatch::Insert(6),
+            LinePatch::Insert(7),
+            LinePatch::Delete(15),
+            LinePatch::Insert(15),
+        ]);
+        assert_eq!(patch.rewritten_lines(), vec![7, 15]);
+    }
+
+    #[test]
+    fn rewritten_lines_need_the_insertion_in_place() {
+        // Lines 2 and 3 are deleted, and a line is inserted after the unchanged line 4
+        let patch = file_patch(vec![
+            LinePatch::Delete(2),
+            LinePatch::Delete(3),
+            LinePatch::Insert(3),
+        ]);
+        assert!(patch.rewritten_lines().is_empty());
+
+        let patch = file_patch(vec![
+            LinePatch::Insert(0),
+            LinePatch::Delete(2),
+            LinePatch::Delete(3),
+            LinePatch::Insert(3),
+        ]);
+        assert_eq!(patch.rewritten_lines(), vec![2, 3]);
+    }
+}
@@ -1,71 +1,311 @@
-use git2::{Commit, Error, Oid, Repository};
+use git2::{Commit, Oid, Repository, RevparseMode, Signature, Tree};
 
 use std::borrow::Cow;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
 use std::path::Path;
 
+use super::attributes::GeneratedFiles;
+use super::error::ConstatError;
+use super::mailmap::{split_identity, Mailmap};
 use super::patch::TreePatch;
 
 use chrono::{DateTime, Duration, TimeZone, Utc};
 
 use rayon::prelude::*;
 
+use serde::{Deserialize, Serialize};
+
+/// How the lines are credited when a commit has `Co-authored-by:` trailers
+#[derive(Clone, Copy, PartialEq, Hash, Debug, Default)]
+pub enum CoAuthorCredit {
+    /// Only the commit author owns the lines
+    #[default]
+    Ignore,
+    /// The lines are split evenly among the author and all the co-authors
+    Split,
+    /// Each of the author and co-authors owns all the lines
+    Duplicate,
+}
+
+/// An entry of the author table, used to persist the author ids
+#[derive(Serialize, Deserialize)]
+pub(super) enum AuthorEntry {
+    Identity(String, String),
+    Group(Vec<u32>),
+}
+
 #[derive(Default)]
 struct AuthorCollection {
-    name_id_map: HashMap<String, u32>,
+    name_id_map: HashMap<(String, String), u32>,
     id_name_map: Vec<String>,
+    group_id_map: HashMap<Vec<u32>, u32>,
+    group_members: HashMap<u32, Vec<u32>>,
 }
 
 impl AuthorCollection {
-    fn query_id(&mut self, name: &str) -> u32 {
-        if let Some(&result) = self.name_id_map.get(name) {
+    fn query_id(&mut self, name: &str, email: &str) -> u32 {
+        let key = (name.to_string(), email.to_lowercase());
+        if let Some(&result) = self.name_id_map.get(&key) {
             result
         } else {
             let ret = self.id_name_map.len() as u32;
-            self.name_id_map.insert(name.to_string(), ret);
+            self.name_id_map.insert(key, ret);
             self.id_name_map.push(name.to_string());
             ret
         }
     }
+    fn query_group_id(&mut self, members: Vec<u32>) -> u32 {
+        if let Some(&result) = self.group_id_map.get(&members) {
+            result
+        } else {
+            let ret = self.id_name_map.len() as u32;
+            let name = members
+                .iter()
+                .map(|&id| self.id_name_map[id as usize].as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+            self.id_name_map.push(name);
+            self.group_id_map.insert(members.clone(), ret);
+            self.group_members.insert(ret, members);
+            ret
+        }
+    }
     #[allow(dead_code)]
     fn query_name(&self, id: u32) -> Option<&str> {
         self.id_name_map.get(id as usize).map(AsRef::as_ref)
     }
+    fn export(&self) -> Vec<AuthorEntry> {
+        let mut ret: Vec<_> = self.id_name_map.iter().map(|_| None).collect();
+        for ((name, email), &id) in self.name_id_map.iter() {
+            ret[id as usize] = Some(AuthorEntry::Identity(name.clone(), email.clone()));
+        }
+        for (&id, members) in self.group_members.iter() {
+            ret[id as usize] = Some(AuthorEntry::Group(members.clone()));
+        }
+        ret.into_iter().map(Option::unwrap).collect()
+    }
 }
 
 pub struct GitRepo {
     inner: Repository,
     authors: RefCell<AuthorCollection>,
+    mailmap: Mailmap,
+    co_author_credit: CoAuthorCredit,
+    generated_files: RefCell<GeneratedFiles>,
+}
+
+fn load_repo_mailmap(repo: &Repository) -> Mailmap {
+    let mut mailmap = Mailmap::default();
+
+    if let Some(workdir) = repo.workdir() {
+        if let Ok(content) = std::fs::read_to_string(workdir.join(".mailmap")) {
+            mailmap.parse(&content);
+        }
+    } else if let Ok(blob) = repo
+        .revparse_single("HEAD:.mailmap")
+        .and_then(|obj| obj.peel_to_blob())
+    {
+        mailmap.parse(&String::from_utf8_lossy(blob.content()));
+    }
+
+    if let Ok(path) = repo.config().and_then(|cfg| cfg.get_path("mailmap.file")) {
+        if let Ok(content) = std::fs::read_to_string(path) {
+            mailmap.parse(&content);
+        }
+    }
+
+    mailmap
+}
+
+/// Parse the `Co-authored-by:` trailers of a commit message into the name and email pairs
+fn co_author_trailers(message: &str) -> impl Iterator<Item = (Option<String>, String)> + '_ {
+    message.lines().filter_map(|line| {
+        let line = line.trim();
+        if !line
+            .get(..15)
+            .map_or(false, |key| key.eq_ignore_ascii_case("co-authored-by:"))
+        {
+            return None;
+        }
+        split_identity(&line[15..]).map(|(name, email, _)| (name, email))
+    })
 }
 
 impl GitRepo {
+    /// Query the id of a pseudo author which doesn't have an email, for example "Older Code"
     pub fn query_author_id(&self, author: &str) -> u32 {
-        self.authors.borrow_mut().query_id(author)
+        self.authors.borrow_mut().query_id(author, "")
+    }
+
+    /// Query the id of an identity, the identity is resolved through the mailmap first
+    pub fn query_identity_id(&self, name: &str, email: &str) -> u32 {
+        let (name, email) = self.mailmap.resolve(name, email);
+        self.authors.borrow_mut().query_id(&name, &email)
+    }
+
+    fn query_signature_id(&self, signature: &Signature) -> u32 {
+        self.query_identity_id(
+            signature.name().unwrap_or("<Unknown>"),
+            signature.email().unwrap_or(""),
+        )
+    }
+
+    /// Query the id that owns the lines introduced by the commit. When co-authors are credited,
+    /// this is the id of the group formed by the author and all the co-authors.
+    fn query_commit_author_id(&self, commit: &Commit) -> u32 {
+        let author_id = self.query_signature_id(&commit.author());
+
+        if self.co_author_credit == CoAuthorCredit::Ignore {
+            return author_id;
+        }
+
+        let mut members = vec![author_id];
+
+        for (name, email) in co_author_trailers(commit.message().unwrap_or("")) {
+            let id = self.query_identity_id(name.as_deref().unwrap_or("<Unknown>"), &email);
+            if !members.contains(&id) {
+                members.push(id);
+            }
+        }
+
+        if members.len() == 1 {
+            author_id
+        } else {
+            self.authors.borrow_mut().query_group_id(members)
+        }
+    }
+
+    /// The digest of the settings that affects how the authors are identified
+    pub(super) fn identity_digest(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.mailmap.digest().hash(&mut hasher);
+        self.co_author_credit.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    pub(super) fn export_authors(&self) -> Vec<AuthorEntry> {
+        self.authors.borrow().export()
+    }
+
+    /// Add the exported author table to this repository, returns the new id of each entry
+    pub(super) fn import_authors(&self, entries: &[AuthorEntry]) -> Vec<u32> {
+        let mut authors = self.authors.borrow_mut();
+        let mut ret = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let id = match entry {
+                AuthorEntry::Identity(name, email) => authors.query_id(name, email),
+                AuthorEntry::Group(members) => authors
+                    .query_group_id(members.iter().map(|&id| ret[id as usize]).collect()),
+            };
+            ret.push(id);
+        }
+        ret
+    }
+
+    pub fn set_co_author_credit(&mut self, credit: CoAuthorCredit) {
+        self.co_author_credit = credit;
+    }
+
+    /// Distribute the lines counted by the author ids, some of which may be co-author groups, to
+    /// the group members, returns the pairs of author id and line count.
+    pub(super) fn credit_stat(&self, stat: Vec<u32>, seed: u64) -> Vec<(u32, u32)> {
+        let mut ret = vec![0; stat.len()];
+
+        for (id, count) in stat.into_iter().enumerate() {
+            for (member, share) in self.credit_lines(id as u32, count, seed) {
+                ret[member as usize] += share;
+            }
+        }
+
+        let authors = self.authors.borrow();
+        ret.into_iter()
+            .enumerate()
+            .filter(|(id, _)| !authors.group_members.contains_key(&(*id as u32)))
+            .map(|(id, count)| (id as u32, count))
+            .collect()
+    }
+
+    /// Distribute the lines owned by a single author id, which may be a co-author group, to the
+    /// actual authors. When the lines are split, the remainder goes to the members starting from
+    /// the seed, so that it's spread over the members when the lines are credited piece by piece.
+    pub fn credit_lines(&self, id: u32, count: u32, seed: u64) -> Vec<(u32, u32)> {
+        let authors = self.authors.borrow();
+        if let Some(members) = authors.group_members.get(&id) {
+            let size = members.len() as u32;
+            let first = (seed % size as u64) as u32;
+            members
+                .iter()
+                .enumerate()
+                .map(|(idx, &member)| {
+                    let rank = (idx as u32 + size - first) % size;
+                    let share = match self.co_author_credit {
+                        CoAuthorCredit::Duplicate => count,
+                        _ if rank < count % size => count / size + 1,
+                        _ => count / size,
+                    };
+                    (member, share)
+                })
+                .collect()
+        } else {
+            vec![(id, count)]
+        }
+    }
+
+    /// Load an additional mailmap file, which takes precedence over the mailmap of the repository
+    pub fn load_mailmap<P: AsRef<Path>>(&mut self, path: P) -> Result<(), ConstatError> {
+        let content = std::fs::read_to_string(path.as_ref())
+            .map_err(|e| ConstatError::Io(path.as_ref().to_owned(), e))?;
+        self.mailmap.parse(&content);
+        Ok(())
+    }
+
+    /// Map an identity, either an email or `Name <email>`, to the canonical name, which takes
+    /// precedence over the mailmap files
+    pub fn add_alias(&mut self, name: &str, identity: &str) {
+        if identity.contains('<') {
+            self.mailmap.parse(&format!("{} <> {}", name, identity));
+        } else {
+            self.mailmap.parse(&format!("{} <{}>", name, identity));
+        }
     }
 
     pub fn query_author_name(&self, id: u32) -> Option<String> {
         self.authors.borrow().query_name(id).map(|r| r.to_owned())
     }
 
+    /// The emails of each author name, an author may use multiple emails
+    pub fn author_emails(&self) -> HashMap<String, Vec<String>> {
+        let mut ret: HashMap<String, Vec<String>> = HashMap::new();
+        for (name, email) in self.authors.borrow().name_id_map.keys() {
+            if !email.is_empty() {
+                ret.entry(name.clone()).or_default().push(email.clone());
+            }
+        }
+        ret
+    }
+
     fn get_patch(
         &self,
         old_commit: Option<&Commit>,
         new_commit: &Commit,
-    ) -> Result<TreePatch, Error> {
-        let old_tree = old_commit.map(|c| c.tree().unwrap());
-        let new_tree = new_commit.tree().unwrap();
-        let old_aid =
-            old_commit.map(|c| self.query_author_id(c.author().name().unwrap_or("<Unknown>")));
-        let new_aid = self.query_author_id(new_commit.author().name().unwrap_or("<Unknown>"));
+    ) -> Result<TreePatch, ConstatError> {
+        let old_tree = match old_commit {
+            Some(c) => Some(c.tree().map_err(ConstatError::CorruptObject)?),
+            None => None,
+        };
+        let new_tree = new_commit.tree().map_err(ConstatError::CorruptObject)?;
+        let old_aid = old_commit.map(|c| self.query_commit_author_id(c));
+        let new_aid = self.query_commit_author_id(new_commit);
         let mut diff_option = git2::DiffOptions::new();
         diff_option.skip_binary_check(true);
-        let mut diff = self.inner.diff_tree_to_tree(
-            old_tree.as_ref(),
-            Some(&new_tree),
-            Some(&mut diff_option),
-        )?;
-        diff.find_similar(None)?;
+        let mut diff = self
+            .inner
+            .diff_tree_to_tree(old_tree.as_ref(), Some(&new_tree), Some(&mut diff_option))
+            .map_err(ConstatError::DiffFailure)?;
+        diff.find_similar(None).map_err(ConstatError::DiffFailure)?;
         let ret = RefCell::new(TreePatch::empty(new_aid, old_aid));
 
         diff.foreach(
@@ -84,26 +324,41 @@ impl GitRepo {
                     .push_line_diff(&l);
                 true
             }),
-        )?;
+        )
+        .map_err(ConstatError::DiffFailure)?;
 
         ret.borrow_mut().sort_patches();
 
         Ok(ret.into_inner())
     }
 
-    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
-        let inner = Repository::open(path.as_ref())?;
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, ConstatError> {
+        let inner = Repository::open(path.as_ref())
+            .map_err(|e| ConstatError::RepoNotFound(path.as_ref().to_owned(), e))?;
         Ok(Self {
+            mailmap: load_repo_mailmap(&inner),
             inner,
             authors: RefCell::new(AuthorCollection::default()),
+            co_author_credit: CoAuthorCredit::default(),
+            generated_files: RefCell::new(GeneratedFiles::default()),
         })
     }
 
-    pub fn find_commit<'a>(&self, version: VersionSpec<'a>) -> Result<GitCommit, Error> {
+    pub fn find_commit<'a>(&self, version: VersionSpec<'a>) -> Result<GitCommit<'_>, ConstatError> {
         let commit = match version {
-            VersionSpec::Head => Some(self.inner.head()?.peel_to_commit()?),
+            VersionSpec::Head => Some(
+                self.inner
+                    .head()
+                    .and_then(|head| head.peel_to_commit())
+                    .map_err(|e| ConstatError::BadRevision("HEAD".to_string(), e))?,
+            ),
             VersionSpec::Scratch => None,
-            VersionSpec::Commit(id) => Some(self.inner.find_commit(Oid::from_str(id.as_ref())?)?),
+            VersionSpec::Revision(spec) => Some(
+                self.inner
+                    .revparse_single(spec)
+                    .and_then(|obj| obj.peel_to_commit())
+                    .map_err(|e| ConstatError::BadRevision(spec.to_string(), e))?,
+            ),
         };
 
         Ok(GitCommit {
@@ -111,13 +366,99 @@ impl GitRepo {
             inner: commit,
         })
     }
+
+    /// Resolve a revision which is either a single revision or a range like `v1.0..v2.0`.
+    /// Returns the commit to analyze and the commit the range starts from.
+    pub fn find_range(
+        &self,
+        spec: &str,
+    ) -> Result<(GitCommit<'_>, Option<GitCommit<'_>>), ConstatError> {
+        let bad_revision = |e| ConstatError::BadRevision(spec.to_string(), e);
+        let revspec = self.inner.revparse(spec).map_err(bad_revision)?;
+        let from = revspec
+            .from()
+            .map(|obj| obj.peel_to_commit())
+            .transpose()
+            .map_err(bad_revision)?;
+        let to = revspec
+            .to()
+            .map(|obj| obj.peel_to_commit())
+            .transpose()
+            .map_err(bad_revision)?;
+
+        let (head, base) = if revspec.mode().contains(RevparseMode::SINGLE) {
+            (from, None)
+        } else if revspec.mode().contains(RevparseMode::MERGE_BASE) {
+            let base = match (from.as_ref(), to.as_ref()) {
+                (Some(from), Some(to)) => Some(
+                    self.inner
+                        .merge_base(from.id(), to.id())
+                        .and_then(|id| self.inner.find_commit(id))
+                        .map_err(bad_revision)?,
+                ),
+                _ => None,
+            };
+            (to, base)
+        } else {
+            (to, from)
+        };
+
+        Ok((
+            GitCommit {
+                repo: self,
+                inner: head,
+            },
+            base.map(|inner| GitCommit {
+                repo: self,
+                inner: Some(inner),
+            }),
+        ))
+    }
+
+    /// Collect the commits reachable from `to` but not reachable from `from`
+    pub fn range_commits(
+        &self,
+        from: &GitCommit,
+        to: &GitCommit,
+    ) -> Result<HashSet<Oid>, ConstatError> {
+        let mut walk = self.inner.revwalk().map_err(ConstatError::CorruptObject)?;
+        if let Some(id) = to.id() {
+            walk.push(id).map_err(ConstatError::CorruptObject)?;
+        }
+        if let Some(id) = from.id() {
+            walk.hide(id).map_err(ConstatError::CorruptObject)?;
+        }
+        walk.map(|id| id.map_err(ConstatError::CorruptObject))
+            .collect()
+    }
+
+    /// List the tags matching the glob pattern and the time of the commits they point to,
+    /// sorted by the time. The tags that don't point to a commit are ignored.
+    pub fn tags(&self, pattern: Option<&str>) -> Result<Vec<(String, DateTime<Utc>)>, ConstatError> {
+        let names = self
+            .inner
+            .tag_names(pattern)
+            .map_err(ConstatError::CorruptObject)?;
+        let mut ret: Vec<_> = names
+            .iter()
+            .flatten()
+            .filter_map(|name| {
+                let object = self.inner.revparse_single(&format!("refs/tags/{}", name)).ok()?;
+                let commit = object.peel_to_commit().ok()?;
+                let time = Utc.timestamp_opt(commit.time().seconds(), 0).single()?;
+                Some((name.to_string(), time))
+            })
+            .collect();
+        ret.sort_by_key(|(_, time)| *time);
+        Ok(ret)
+    }
 }
 
-#[allow(dead_code)]
 pub enum VersionSpec<'a> {
     Head,
     Scratch,
-    Commit(&'a str),
+    /// Any revision git understands, e.g. a commit id, a branch, a tag or `HEAD~50`
+    Revision(&'a str),
 }
 
 pub struct HistoryGraph<'a> {
@@ -138,6 +479,10 @@ impl<'a> HistoryGraph<'a> {
         self.commits.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.commits.is_empty()
+    }
+
     pub fn get_commit(&self, idx: usize) -> Option<GitCommit<'a>> {
         self.commits.get(idx).map(|commit| GitCommit {
             repo: self.repo,
@@ -205,6 +550,11 @@ pub struct GitCommit<'a> {
 }
 
 impl<'a> GitCommit<'a> {
+    /// The repository that the commit belongs to
+    pub fn repo(&self) -> &'a GitRepo {
+        self.repo
+    }
+
     pub fn id(&self) -> Option<Oid> {
         if let Some(inner) = self.inner.as_ref() {
             Some(inner.id())
@@ -221,6 +571,30 @@ impl<'a> GitCommit<'a> {
         }
     }
 
+    /// Follow the first parents until we reach a commit that is not later than the given time
+    /// The commits on the first-parent chain from this commit back to the root commit, this
+    /// commit comes first
+    pub fn first_parent_chain(&self) -> Vec<GitCommit<'a>> {
+        let mut ret = vec![];
+        let mut inner = self.inner.clone();
+        while let Some(commit) = inner {
+            inner = commit.parent(0).ok();
+            ret.push(GitCommit {
+                repo: self.repo,
+                inner: Some(commit),
+            });
+        }
+        ret
+    }
+
+    pub fn last_before(&self, time: DateTime<Utc>) -> GitCommit<'a> {
+        let mut ret = self.clone();
+        while ret.get_timestamp().map_or(false, |ts| ts > time) {
+            ret.inner = ret.inner.and_then(|commit| commit.parent(0).ok());
+        }
+        ret
+    }
+
     #[allow(dead_code)]
     pub fn get_author_by_name(&self, name: &str) -> u32 {
         self.repo.query_author_id(name)
@@ -228,8 +602,7 @@ impl<'a> GitCommit<'a> {
 
     pub fn author_id(&self) -> u32 {
         if let Some(git_obj) = self.inner.as_ref() {
-            self.repo
-                .query_author_id(git_obj.author().name().unwrap_or("<Unknown>"))
+            self.repo.query_commit_author_id(git_obj)
         } else {
             self.repo.query_author_id("<Unknown>")
         }
@@ -238,12 +611,48 @@ impl<'a> GitCommit<'a> {
     #[allow(dead_code)]
     pub fn author_name(&self) -> String {
         if let Some(git_obj) = self.inner.as_ref() {
-            git_obj.author().name().unwrap_or("<Unknown>").to_string()
+            let author = git_obj.author();
+            self.repo
+                .mailmap
+                .resolve(author.name().unwrap_or("<Unknown>"), author.email().unwrap_or(""))
+                .0
         } else {
             "<Unknown>".to_string()
         }
     }
 
+    /// The tree of this commit, None for the empty commit
+    pub fn tree(&self) -> Option<Tree<'a>> {
+        self.inner.as_ref()?.tree().ok()
+    }
+
+    /// Whether the file is marked as `linguist-generated`, `linguist-vendored` or `-diff` by the
+    /// `.gitattributes` files in the tree of this commit
+    pub fn is_generated(&self, tree: &Tree, path: &Path) -> bool {
+        self.repo
+            .generated_files
+            .borrow_mut()
+            .is_generated(&self.repo.inner, tree, path)
+    }
+
+    /// The first line of the file in this commit, None if the file doesn't exist or is binary
+    pub fn first_line(&self, path: &Path) -> Option<String> {
+        let blob = self
+            .inner
+            .as_ref()?
+            .tree()
+            .ok()?
+            .get_path(path)
+            .ok()?
+            .to_object(&self.repo.inner)
+            .ok()?
+            .peel_to_blob()
+            .ok()?;
+        let content = blob.content();
+        let line = &content[..content.iter().position(|&b| b == b'\n').unwrap_or(content.len())];
+        std::str::from_utf8(line).ok().map(ToOwned::to_owned)
+    }
+
     fn find_effctive_ancestors<'b>(commit: &Commit<'b>) -> Vec<Commit<'b>> {
         let mut ret = vec![];
         let mut queue = std::collections::VecDeque::new();
@@ -272,7 +681,7 @@ impl<'a> GitCommit<'a> {
     pub fn topological_sort<Pred: Fn(&GitCommit) -> bool + Clone>(
         self,
         predict: Pred,
-    ) -> Result<HistoryGraph<'a>, Error> {
+    ) -> Result<HistoryGraph<'a>, ConstatError> {
         if self.inner.is_none() {
             return Ok(HistoryGraph {
                 repo: self.repo,
@@ -359,16 +768,16 @@ impl<'a> GitCommit<'a> {
         &self,
         base: BaseIter,
         verbose: bool,
-    ) -> Result<Vec<TreePatch>, Error> {
+    ) -> Result<Vec<TreePatch>, ConstatError> {
         let mut ret = vec![];
         if let Some(root) = self.inner.as_ref() {
             let base: Vec<_> = base.into_iter().collect();
             if base.len() > 1 {
-                let new_aid = self.repo.query_author_id(root.author().name().unwrap_or("<Unknown>"));
+                let new_aid = self.repo.query_commit_author_id(root);
                 
                 let base:Vec<_> = base.into_iter().enumerate().map(|(id, x)| {
                     let old_aid =
-                        x.inner.as_ref().map(|c| self.repo.query_author_id(c.author().name().unwrap_or("<Unknown>")));
+                        x.inner.as_ref().map(|c| self.repo.query_commit_author_id(c));
                     (id, self.repo.inner.path().to_owned(), x.id(), old_aid, new_aid)
                 }).collect();
                 
@@ -377,14 +786,14 @@ impl<'a> GitCommit<'a> {
                     if verbose {
                         eprintln!("Comparing diff between {} and {} in parallel", oid.unwrap_or(Oid::zero()), root_id);
                     }
-                    let repo = GitRepo::open(path).unwrap();
-                    let commit = repo.inner.find_commit(oid.unwrap()).ok();
-                    let root = repo.inner.find_commit(root_id).unwrap();
-                    let mut patch = repo.get_patch(commit.as_ref(), &root).unwrap();
+                    let repo = GitRepo::open(path)?;
+                    let commit = oid.map(|oid| repo.inner.find_commit(oid)).transpose().map_err(ConstatError::CorruptObject)?;
+                    let root = repo.inner.find_commit(root_id).map_err(ConstatError::CorruptObject)?;
+                    let mut patch = repo.get_patch(commit.as_ref(), &root)?;
                     patch.old_author = oa;
                     patch.new_author = na;
-                    (id, patch)
-                }).collect();
+                    Ok((id, patch))
+                }).collect::<Result<_, ConstatError>>()?;
                 res_buf.sort_by_key(|(id, _)| *id);
                 for (_, patch) in res_buf.into_iter() {
                     ret.push(patch);
@@ -413,9 +822,9 @@ impl<'a> GitCommit<'a> {
         Ok(ret)
     }
 
-    pub fn tree_walk<F: FnMut(&Path, usize)>(&self, mut func: F) {
+    pub fn tree_walk<F: FnMut(&Path, usize)>(&self, mut func: F) -> Result<(), ConstatError> {
         if let Some(inner) = self.inner.as_ref() {
-            let tree = inner.tree().unwrap();
+            let tree = inner.tree().map_err(ConstatError::CorruptObject)?;
             let mut last_level = 0;
             tree.walk(git2::TreeWalkMode::PostOrder, move |root, entry| {
                 let full_path = format!("{}{}", root, entry.name().unwrap_or(""));
@@ -437,7 +846,72 @@ impl<'a> GitCommit<'a> {
                 }
                 last_level = levels;
                 git2::TreeWalkResult::Ok
-            }).unwrap();
+            }).map_err(ConstatError::CorruptObject)?;
         }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn co_author_trailers_are_parsed() {
+        let message = "Fix the parser\n\nCo-authored-by: Jane Doe <jane@example.com>\n\
+                       co-authored-by:<john@example.com>\n\
+                       Signed-off-by: Bob <bob@example.com>\n\
+                       Co-authored-by: nobody\n";
+        let trailers: Vec<_> = co_author_trailers(message).collect();
+        assert_eq!(
+            trailers,
+            vec![
+                (Some("Jane Doe".to_string()), "jane@example.com".to_string()),
+                (None, "john@example.com".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn co_author_trailers_need_the_key_at_line_start() {
+        let message = "Mention Co-authored-by: Jane Doe <jane@example.com> inline\n";
+        assert_eq!(co_author_trailers(message).count(), 0);
+    }
+
+    #[test]
+    fn tags_are_filtered_by_glob_and_sorted_by_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let git = git2::Repository::init(dir.path()).unwrap();
+        let git_tree = git
+            .find_tree(git.index().unwrap().write_tree().unwrap())
+            .unwrap();
+        let old = git2::Signature::new("Jane Doe", "jane@example.com", &git2::Time::new(1000, 0))
+            .unwrap();
+        let new = git2::Signature::new("Jane Doe", "jane@example.com", &git2::Time::new(2000, 0))
+            .unwrap();
+        let first = git
+            .commit(None, &old, &old, "First", &git_tree, &[])
+            .unwrap();
+        let first = git.find_commit(first).unwrap();
+        let second = git
+            .commit(None, &new, &new, "Second", &git_tree, &[&first])
+            .unwrap();
+        let second = git.find_commit(second).unwrap();
+        git.tag_lightweight("v2.0", second.as_object(), false)
+            .unwrap();
+        git.tag("v1.0", first.as_object(), &new, "Release", false)
+            .unwrap();
+        git.tag_lightweight("nightly", second.as_object(), false)
+            .unwrap();
+
+        let repo = GitRepo::open(dir.path()).unwrap();
+        let names = |pattern| -> Vec<_> {
+            let tags = repo.tags(pattern).unwrap();
+            tags.into_iter().map(|(name, _)| name).collect()
+        };
+        assert_eq!(names(Some("v*")), vec!["v1.0", "v2.0"]);
+        assert_eq!(names(Some("nightly")), vec!["nightly"]);
+        assert_eq!(names(Some("rc*")), Vec::<String>::new());
+        assert_eq!(names(None).len(), 3);
     }
 }
@@ -1,18 +1,34 @@
-use super::patch::{FilePatch, TreePatch};
-use super::GitCommit;
+use super::patch::{FilePatch, LinePatch, TreePatch};
+use super::{ConstatError, GitCommit, GitRepo};
 use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 use std::path::{Path, PathBuf};
 
-#[derive(Clone)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct LineBlock {
     author_id: u32,
+    /// The time when the lines are introduced, in seconds since the Unix epoch
+    time: i64,
     size: u32,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Tree<'a> {
     root: HashMap<PathBuf, Cow<'a, Vec<LineBlock>>>,
+    /// The lines removed by the first-parent patch that produced this tree
+    #[serde(skip)]
+    removed: HashMap<PathBuf, Vec<LineBlock>>,
+    /// The number of lines added by the patch that produced this tree, merges don't add lines
+    #[serde(skip)]
+    added: HashMap<PathBuf, u32>,
+    /// The removed lines that are replaced by the lines added in their place, merges don't
+    /// rewrite lines
+    #[serde(skip)]
+    rewritten: HashMap<PathBuf, Vec<LineBlock>>,
 }
 
 fn get_patch_filename_list(patch: &[TreePatch]) -> Vec<(Option<&Path>, Option<&Path>)> {
@@ -49,6 +65,7 @@ fn get_related_authors(patches: &[TreePatch]) -> Vec<u32> {
 #[derive(Debug)]
 struct Addition {
     author: u32,
+    time: i64,
     line: u32,
 }
 
@@ -56,6 +73,7 @@ fn merge_file_patch<'a>(
     patches: impl Iterator<Item = (u32, Option<&'a FilePatch>)>,
     trees: impl Iterator<Item = Option<&'a [LineBlock]>>,
     merger: u32,
+    time: i64,
 ) -> Vec<Addition> {
     let mut patches: Vec<_> = patches
         .map(|(author, patch)| {
@@ -115,18 +133,21 @@ fn merge_file_patch<'a>(
             }
             
             if author_ofs > 0 && author_ofs <= patches.len() {
-                let current_author = if trees[author_ofs as usize - 1].map_or(true, |x| x.is_empty()) {
-                    patches[author_ofs as usize - 1].0
+                let (current_author, current_time) = if trees[author_ofs as usize - 1].map_or(true, |x| x.is_empty()) {
+                    (patches[author_ofs as usize - 1].0, time)
                 } else {
-                    trees[author_ofs as usize - 1].as_ref().unwrap()[0].author_id
+                    let block = &trees[author_ofs as usize - 1].as_ref().unwrap()[0];
+                    (block.author_id, block.time)
                 };
                 ret.push(Addition {
                     line: next_line as u32,
                     author: current_author,
+                    time: current_time,
                 });
             } else {
                 ret.push(Addition {
                     author: merger,
+                    time,
                     line: next_line as u32,
                 })
             }
@@ -141,22 +162,45 @@ impl<'a> Tree<'a> {
     pub fn empty() -> Self {
         Tree {
             root: HashMap::new(),
+            removed: HashMap::new(),
+            added: HashMap::new(),
+            rewritten: HashMap::new(),
         }
     }
 
-    pub fn from_commit<'b>(commit: &'b GitCommit<'b>, author: u32, verbose: bool) -> Self {
+    pub fn from_commit<'b>(
+        commit: &'b GitCommit<'b>,
+        author: u32,
+        verbose: bool,
+    ) -> Result<Self, ConstatError> {
         let mut root = HashMap::new();
+        let time = commit.get_timestamp().map_or(0, |ts| ts.timestamp());
         if verbose {
             println!("Enumerate tree {}", commit.id().unwrap_or(git2::Oid::zero()));
         }
         commit.tree_walk(|path, line| {
             root.insert(path.to_owned(), Cow::Owned(vec![LineBlock{
                 author_id: author,
+                time,
                 size: line as u32,
             }]));
-        });
+        })?;
 
-        Tree{root}
+        Ok(Tree {
+            root,
+            removed: HashMap::new(),
+            added: HashMap::new(),
+            rewritten: HashMap::new(),
+        })
+    }
+
+    /// Replace the author ids with the ones in the map, which is indexed by the old author id
+    pub(super) fn remap_authors(&mut self, map: &[u32]) {
+        for file in self.root.values_mut() {
+            for block in file.to_mut().iter_mut() {
+                block.author_id = map[block.author_id as usize];
+            }
+        }
     }
 
     fn copy_from_old_tree(
@@ -197,7 +241,7 @@ impl<'a> Tree<'a> {
     fn compress_abstract_file(file: &mut Vec<LineBlock>) {
         let mut j = 1;
         for i in 1..file.len() {
-            if file[j - 1].author_id == file[i].author_id {
+            if file[j - 1].author_id == file[i].author_id && file[j - 1].time == file[i].time {
                 file[j - 1].size += file[i].size;
             } else {
                 file[j] = file[i].clone();
@@ -218,11 +262,13 @@ impl<'a> Tree<'a> {
                 if last_begin < diff[idx].line {
                     buffer.push(LineBlock {
                         author_id: block.author_id,
+                        time: block.time,
                         size: diff[idx].line - last_begin,
                     });
                 }
                 buffer.push(LineBlock {
                     author_id: diff[idx].author,
+                    time: diff[idx].time,
                     size: 1,
                 });
                 last_begin = diff[idx].line + 1;
@@ -231,6 +277,7 @@ impl<'a> Tree<'a> {
             if last_begin < last_end {
                 buffer.push(LineBlock {
                     author_id: block.author_id,
+                    time: block.time,
                     size: last_end - last_begin,
                 });
             }
@@ -242,12 +289,58 @@ impl<'a> Tree<'a> {
         buffer
     }
 
-    pub fn analyze_patch(trees: &[&Self], patch: &[TreePatch], merger: u32) -> Tree<'a> {
+    /// The lines removed from the first parent by a merge are only counted when all the other
+    /// parents still have them, otherwise they are removed on the merged branch and counted there
+    fn removed_by_merge<'b>(
+        removed: Vec<LineBlock>,
+        first: &[LineBlock],
+        others: impl Iterator<Item = &'b [LineBlock]>,
+    ) -> Vec<LineBlock> {
+        let count_lines = |file: &[LineBlock]| {
+            let mut ret = HashMap::new();
+            for block in file {
+                *ret.entry((block.author_id, block.time)).or_insert(0) += block.size;
+            }
+            ret
+        };
+        let owned = count_lines(first);
+        let removed_lines = count_lines(&removed);
+        let mut remaining = removed_lines.clone();
+        for file in others {
+            let kept = count_lines(file);
+            for (key, count) in remaining.iter_mut() {
+                let missing = owned
+                    .get(key)
+                    .cloned()
+                    .unwrap_or(0)
+                    .saturating_sub(kept.get(key).cloned().unwrap_or(0));
+                *count = (*count).min(removed_lines[key].saturating_sub(missing));
+            }
+        }
+        removed
+            .into_iter()
+            .filter_map(|block| {
+                let count = remaining.get_mut(&(block.author_id, block.time))?;
+                let size = block.size.min(*count);
+                *count -= size;
+                if size > 0 {
+                    Some(LineBlock { size, ..block })
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    pub fn analyze_patch(trees: &[&Self], patch: &[TreePatch], merger: u32, time: i64) -> Tree<'a> {
         let files = get_patch_filename_list(patch);
         let authors = get_related_authors(patch);
         let mut file_iters: Vec<_> = patch.iter().map(|x| x.files().iter().peekable()).collect();
 
         let mut ret = trees[0].clone();
+        ret.removed.clear();
+        ret.added.clear();
+        ret.rewritten.clear();
 
         for (old, new) in files {
             let mut file_patch = Vec::with_capacity(patch.len());
@@ -262,8 +355,27 @@ impl<'a> Tree<'a> {
                 }
             }
 
+            if let (Some(patch), Some(new), 1) = (file_patch[0], new, trees.len()) {
+                let added = patch.patch.iter().filter(|lp| matches!(lp, LinePatch::Insert(_))).count();
+                if added > 0 {
+                    ret.added.insert(new.to_owned(), added as u32);
+                }
+            }
+
             if let Some(patch) = file_patch[0] {
+                let rewritten_lines = if trees.len() == 1 {
+                    patch.rewritten_lines()
+                } else {
+                    vec![]
+                };
                 let mut patch = patch.patch[..].iter().peekable();
+                let mut removed = vec![];
+                let mut rewritten = vec![];
+                if let (Some(old), None) = (old, new) {
+                    if let Some(file) = trees[0].root.get(old) {
+                        removed.extend(file.iter().cloned());
+                    }
+                }
                 if let Some(file) = ret.copy_from_old_tree(&trees[0], old, new) {
                     let mut new_base = 0;
                     let mut old_base = 0;
@@ -278,6 +390,10 @@ impl<'a> Tree<'a> {
                             if let Some(line) = line_diff.old_lineno() {
                                 if line > old_base {
                                     new_size -= 1;
+                                    removed.push(LineBlock { size: 1, ..block.clone() });
+                                    if rewritten_lines.binary_search(&line).is_ok() {
+                                        rewritten.push(LineBlock { size: 1, ..block.clone() });
+                                    }
                                 }
                             } else if let Some(line) = line_diff.new_lineno() {
                                 if line > new_base {
@@ -294,6 +410,7 @@ impl<'a> Tree<'a> {
                             if file.is_empty() {
                                 file.push(LineBlock {
                                     author_id: merger,
+                                    time,
                                     size: 0,
                                 });
                             }
@@ -301,13 +418,28 @@ impl<'a> Tree<'a> {
                         }
                     }
                 }
+                if trees.len() > 1 {
+                    let mut parent_files = trees.iter().map(|t| {
+                        old.and_then(|old| t.root.get(old)).map_or(&[][..], |f| f.as_slice())
+                    });
+                    let first = parent_files.next().unwrap();
+                    removed = Self::removed_by_merge(removed, first, parent_files);
+                }
+                if !removed.is_empty() {
+                    Self::compress_abstract_file(&mut removed);
+                    ret.removed.insert(new.or(old).unwrap().to_owned(), removed);
+                }
+                if !rewritten.is_empty() {
+                    Self::compress_abstract_file(&mut rewritten);
+                    ret.rewritten.insert(new.or(old).unwrap().to_owned(), rewritten);
+                }
             }
 
             let patch_iter = authors.iter().map(|x| *x).zip(file_patch.into_iter());
 
             let old_files = trees.iter().map(|t| old.map(|old| t.root.get(old).map(|cow| cow.as_ref().as_ref())).flatten());
 
-            let merged_diff = merge_file_patch(patch_iter, old_files, merger);
+            let merged_diff = merge_file_patch(patch_iter, old_files, merger, time);
 
             if let Some(file) = new.map(|p| ret.root.get_mut(p)).flatten() {
                 *file = Cow::Owned(Self::apply_author_diff_to_file(file, merged_diff.as_ref()));
@@ -317,7 +449,9 @@ impl<'a> Tree<'a> {
         ret
     }
 
-    pub fn stat<Predit: Fn(&Path) -> bool>(&self, predict: Predit) -> Vec<u32> {
+    /// Count the lines of each author, the lines of a co-author group are credited to its members
+    /// as a whole, returns the pairs of author id and line count
+    pub fn stat<Predit: Fn(&Path) -> bool>(&self, predict: Predit, repo: &GitRepo) -> Vec<(u32, u32)> {
         let mut ret = vec![];
         for (path, file) in self.root.iter() {
             if !predict(path) {
@@ -330,10 +464,129 @@ impl<'a> Tree<'a> {
                 ret[block.author_id as usize] += block.size;
             }
         }
+        repo.credit_stat(ret, 0)
+    }
+
+    /// The stat of the files grouped by the key of each file, for example the language
+    pub fn stat_by<Predit, Key, K>(
+        &self,
+        predict: Predit,
+        key: Key,
+        repo: &GitRepo,
+    ) -> HashMap<K, Vec<(u32, u32)>>
+    where
+        Predit: Fn(&Path) -> bool,
+        Key: Fn(&Path) -> K,
+        K: Eq + Hash,
+    {
+        let mut ret: HashMap<K, Vec<u32>> = HashMap::new();
+        for (path, file) in self.root.iter() {
+            if !predict(path) {
+                continue;
+            }
+            let stat = ret.entry(key(path)).or_default();
+            for block in file.as_ref() {
+                if stat.len() < block.author_id as usize + 1 {
+                    stat.resize(block.author_id as usize + 1, 0);
+                }
+                stat[block.author_id as usize] += block.size;
+            }
+        }
+        ret.into_iter()
+            .map(|(key, stat)| (key, repo.credit_stat(stat, 0)))
+            .collect()
+    }
+
+    /// Count the lines of the files grouped by the key of each file, regardless of the authors
+    pub fn lines_by<Predit, Key, K>(&self, predict: Predit, key: Key) -> HashMap<K, u32>
+    where
+        Predit: Fn(&Path) -> bool,
+        Key: Fn(&Path) -> K,
+        K: Eq + Hash,
+    {
+        let mut ret = HashMap::new();
+        for (path, file) in self.root.iter() {
+            if !predict(path) {
+                continue;
+            }
+            *ret.entry(key(path)).or_insert(0) += file.iter().map(|block| block.size).sum::<u32>();
+        }
+        ret
+    }
+
+    /// Count the lines grouped by the cohort derived from the time they are introduced
+    pub fn cohort_stat<Predit, Cohort, K>(&self, predict: Predit, cohort: Cohort) -> HashMap<K, u32>
+    where
+        Predit: Fn(&Path) -> bool,
+        Cohort: Fn(i64) -> K,
+        K: Hash + Eq,
+    {
+        let mut ret = HashMap::new();
+        for (path, file) in self.root.iter() {
+            if !predict(path) {
+                continue;
+            }
+            for block in file.as_ref() {
+                *ret.entry(cohort(block.time)).or_insert(0) += block.size;
+            }
+        }
         ret
     }
-    #[allow(dead_code)] 
-    pub fn file_stat<P:AsRef<Path>>(&self, p: P) -> Option<Vec<u32>> {
+
+    fn birth_stat_of<'b, Predit: Fn(&Path) -> bool>(
+        files: impl Iterator<Item = (&'b PathBuf, &'b [LineBlock])>,
+        predict: Predit,
+    ) -> HashMap<(u32, i64), u32> {
+        let mut ret = HashMap::new();
+        for (path, file) in files {
+            if !predict(path) {
+                continue;
+            }
+            for block in file {
+                *ret.entry((block.author_id, block.time)).or_insert(0) += block.size;
+            }
+        }
+        ret
+    }
+
+    /// Count the lines grouped by the author and the time they are introduced
+    pub fn birth_stat<Predit: Fn(&Path) -> bool>(&self, predict: Predit) -> HashMap<(u32, i64), u32> {
+        Self::birth_stat_of(self.root.iter().map(|(p, f)| (p, f.as_slice())), predict)
+    }
+
+    /// Same as `birth_stat`, but counts the lines removed by the first-parent patch of the commit
+    pub fn removed_birth_stat<Predit: Fn(&Path) -> bool>(
+        &self,
+        predict: Predit,
+    ) -> HashMap<(u32, i64), u32> {
+        Self::birth_stat_of(self.removed.iter().map(|(p, f)| (p, f.as_slice())), predict)
+    }
+
+    /// Same as `removed_birth_stat`, but only counts the removed lines that are rewritten
+    pub fn rewritten_birth_stat<Predit: Fn(&Path) -> bool>(
+        &self,
+        predict: Predit,
+    ) -> HashMap<(u32, i64), u32> {
+        Self::birth_stat_of(self.rewritten.iter().map(|(p, f)| (p, f.as_slice())), predict)
+    }
+
+    /// Count the lines added by the patch that produced this tree
+    pub fn added_stat<Predit: Fn(&Path) -> bool>(&self, predict: Predit) -> u32 {
+        self.added
+            .iter()
+            .filter(|(path, _)| predict(path))
+            .map(|(_, count)| count)
+            .sum()
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = &Path> {
+        self.root.keys().map(AsRef::as_ref)
+    }
+
+    /// Same as `stat`, but only counts a single file. The remainder of the lines split among a
+    /// co-author group goes to the members in an order derived from the path, so that the first
+    /// member isn't favored in every file.
+    pub fn file_stat<P: AsRef<Path>>(&self, p: P, repo: &GitRepo) -> Option<Vec<(u32, u32)>> {
         if let Some(file) = self.root.get(p.as_ref()) {
             let mut ret = vec![];
             for block in file.as_ref() {
@@ -342,7 +595,9 @@ impl<'a> Tree<'a> {
                 }
                 ret[block.author_id as usize] += block.size;
             }
-            return Some(ret);
+            let mut hasher = DefaultHasher::new();
+            p.as_ref().hash(&mut hasher);
+            return Some(repo.credit_stat(ret, hasher.finish()));
         }
         None
     }
@@ -0,0 +1,59 @@
+use super::options::Period;
+use super::report::print_columns;
+use std::collections::BTreeMap;
+
+/// The lines changed by an author in a commit: the author, the time, the lines added, the lines
+/// removed, and the removed lines of the author themselves and of the others that are replaced
+/// by the lines added in their place, which is stored in the cache
+pub type ChurnRecord = (String, i64, usize, usize, usize, usize);
+
+/// The lines added, removed, own rewritten and others rewritten by an author in a period
+type ChurnCell = (usize, usize, usize, usize);
+
+/// Print the lines added, removed and rewritten by each author in each period. A removed line is
+/// only rewritten when lines are added in its place, so a pure deletion isn't a rewrite. The
+/// authors are sorted by the number of lines they changed in the period, the largest first.
+pub fn print_churn_report(records: &[ChurnRecord], period: Period) {
+    let mut churn: BTreeMap<String, BTreeMap<&str, ChurnCell>> = BTreeMap::new();
+
+    for (author, time, added, deleted, own, others) in records {
+        let cell = churn
+            .entry(period.label(*time))
+            .or_default()
+            .entry(author)
+            .or_default();
+        cell.0 += added;
+        cell.1 += deleted;
+        cell.2 += own;
+        cell.3 += others;
+    }
+
+    let mut rows = vec![vec![
+        "Period".to_string(),
+        "Author".to_string(),
+        "Added".to_string(),
+        "Deleted".to_string(),
+        "Own Rewritten".to_string(),
+        "Others Rewritten".to_string(),
+    ]];
+
+    for (label, authors) in churn {
+        let mut authors: Vec<_> = authors.into_iter().collect();
+        authors.sort_by(|a, b| {
+            let changed = |(added, deleted, _, _): ChurnCell| added + deleted;
+            changed(b.1).cmp(&changed(a.1)).then(a.0.cmp(b.0))
+        });
+        for (author, (added, deleted, own, others)) in authors {
+            rows.push(vec![
+                label.clone(),
+                author.to_string(),
+                added.to_string(),
+                deleted.to_string(),
+                own.to_string(),
+                others.to_string(),
+            ]);
+        }
+    }
+
+    print_columns(&rows, &[false, false, true, true, true, true]);
+}
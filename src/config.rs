@@ -0,0 +1,14 @@
+use serde::de::DeserializeOwned;
+use std::path::Path;
+
+/// Load a TOML or YAML file, the format is decided by the extension and TOML is the default
+pub fn load_file<T: DeserializeOwned, P: AsRef<Path>>(path: P) -> Result<T, String> {
+    let path = path.as_ref();
+    let content = std::fs::read_to_string(path)
+        .map_err(|err| format!("Cannot read {}: {}", path.display(), err))?;
+    let result = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("yml") | Some("yaml") => serde_yaml::from_str(&content).map_err(|err| err.to_string()),
+        _ => toml::from_str(&content).map_err(|err| err.to_string()),
+    };
+    result.map_err(|err| format!("Cannot parse {}: {}", path.display(), err))
+}
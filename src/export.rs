@@ -0,0 +1,47 @@
+use super::plotting::AuthorInfo;
+use serde_json::json;
+use std::fs::File;
+use std::io::{BufWriter, Result, Write};
+use std::path::Path;
+
+fn records(author_info: &AuthorInfo) -> Vec<(&str, String, usize)> {
+    let mut authors: Vec<_> = author_info.iter().collect();
+    authors.sort_by_key(|(name, _)| name.as_str());
+    authors
+        .into_iter()
+        .flat_map(|(name, stat)| {
+            stat.iter()
+                .map(move |(date, &lines)| (name.as_str(), date.format("%Y-%m-%d").to_string(), lines))
+        })
+        .collect()
+}
+
+fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Write the time series as CSV, one row per author and date
+pub fn write_csv<P: AsRef<Path>>(author_info: &AuthorInfo, path: P) -> Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, "author,date,lines")?;
+    for (author, date, lines) in records(author_info) {
+        writeln!(out, "{},{},{}", escape_csv(author), date, lines)?;
+    }
+    out.flush()
+}
+
+/// Write the time series as a JSON array, one object per author and date
+pub fn write_json<P: AsRef<Path>>(author_info: &AuthorInfo, path: P) -> Result<()> {
+    let records: Vec<_> = records(author_info)
+        .into_iter()
+        .map(|(author, date, lines)| json!({ "author": author, "date": date, "lines": lines }))
+        .collect();
+    let mut out = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut out, &records)?;
+    writeln!(out)?;
+    out.flush()
+}
@@ -0,0 +1,116 @@
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::fmt;
+use std::path::Path;
+
+/// The include and exclude patterns of the analyzed files, each pattern is matched like a line of
+/// `.gitignore`, so that a pattern like `src/` matches everything under the directory and a
+/// pattern that starts with `!` negates the patterns before it.
+pub struct PathFilter {
+    includes: Vec<String>,
+    excludes: Vec<String>,
+    include_set: Gitignore,
+    exclude_set: Gitignore,
+}
+
+fn build_set(patterns: &[String]) -> Result<Gitignore, String> {
+    let mut builder = GitignoreBuilder::new("");
+    for pattern in patterns {
+        builder
+            .add_line(None, pattern)
+            .map_err(|err| format!("Invalid path pattern {}: {}", pattern, err))?;
+    }
+    builder.build().map_err(|err| err.to_string())
+}
+
+impl PathFilter {
+    pub fn new(includes: Vec<String>, excludes: Vec<String>) -> Result<Self, String> {
+        Ok(Self {
+            include_set: build_set(&includes)?,
+            exclude_set: build_set(&excludes)?,
+            includes,
+            excludes,
+        })
+    }
+
+    /// Whether the file is included, all the files are included if there's no include pattern
+    pub fn matches(&self, path: &Path) -> bool {
+        let included = self.includes.is_empty()
+            || self
+                .include_set
+                .matched_path_or_any_parents(path, false)
+                .is_ignore();
+        included
+            && !self
+                .exclude_set
+                .matched_path_or_any_parents(path, false)
+                .is_ignore()
+    }
+}
+
+impl fmt::Debug for PathFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PathFilter")
+            .field("includes", &self.includes)
+            .field("excludes", &self.excludes)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path_filter(includes: &[&str], excludes: &[&str]) -> PathFilter {
+        let patterns = |patterns: &[&str]| patterns.iter().map(|p| p.to_string()).collect();
+        PathFilter::new(patterns(includes), patterns(excludes)).unwrap()
+    }
+
+    fn matches(filter: &PathFilter, path: &str) -> bool {
+        filter.matches(Path::new(path))
+    }
+
+    #[test]
+    fn everything_is_included_without_patterns() {
+        let filter = path_filter(&[], &[]);
+        assert!(matches(&filter, "src/main.rs"));
+        assert!(matches(&filter, "README.md"));
+    }
+
+    #[test]
+    fn directory_patterns_match_everything_under_them() {
+        let filter = path_filter(&["src/"], &["src/generated/"]);
+        assert!(matches(&filter, "src/main.rs"));
+        assert!(matches(&filter, "src/analyzer/tree.rs"));
+        assert!(!matches(&filter, "src/generated/schema.rs"));
+        assert!(!matches(&filter, "tests/cli.rs"));
+    }
+
+    #[test]
+    fn negation_only_overrides_the_patterns_before_it() {
+        let filter = path_filter(&[], &["*.json", "!package.json"]);
+        assert!(!matches(&filter, "data/fixture.json"));
+        assert!(matches(&filter, "package.json"));
+        assert!(matches(&filter, "web/package.json"));
+
+        // The later pattern excludes the file again
+        let filter = path_filter(&[], &["*.json", "!package.json", "web/*.json"]);
+        assert!(matches(&filter, "package.json"));
+        assert!(!matches(&filter, "web/package.json"));
+    }
+
+    #[test]
+    fn excludes_take_precedence_over_includes() {
+        let filter = path_filter(&["*.rs", "!build.rs"], &["tests/"]);
+        assert!(matches(&filter, "src/lib.rs"));
+        assert!(!matches(&filter, "build.rs"));
+        assert!(!matches(&filter, "tests/cli.rs"));
+        assert!(!matches(&filter, "README.md"));
+    }
+
+    #[test]
+    fn includes_may_match_no_files() {
+        let filter = path_filter(&["nothing/"], &[]);
+        assert!(!matches(&filter, "src/main.rs"));
+        assert!(!matches(&filter, "README.md"));
+    }
+}
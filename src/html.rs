@@ -0,0 +1,101 @@
+use super::options::ConstatOptions;
+use super::plotting::{
+    chart_title, final_ownership, name_column, repo_name, stack_series, AuthorInfo,
+};
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{BufWriter, Result, Write};
+
+/// The page that renders the chart, the data is substituted for the placeholder
+const TEMPLATE: &str = include_str!("report.html");
+
+const DATA_PLACEHOLDER: &str = "/*CONSTAT_DATA*/null";
+
+/// Write a self-contained HTML page, which plots the series of the chart interactively and lists
+/// the lines owned by every author at the end
+pub fn write_html(
+    author_info: &mut AuthorInfo,
+    markers: &[(String, DateTime<Utc>)],
+    options: &ConstatOptions,
+) -> Result<()> {
+    let owners = final_ownership(author_info, options);
+    let series = stack_series(author_info, options);
+
+    // Every series has a value at each date, the value of a date missing in a series is carried
+    // over from the last date before it
+    let dates: BTreeSet<_> = series
+        .iter()
+        .flat_map(|(_, stat)| stat.iter().map(|(date, _)| *date))
+        .collect();
+    let series: Vec<_> = series
+        .into_iter()
+        .map(|(name, stat)| {
+            let mut stat = stat.into_iter().peekable();
+            let mut value = 0;
+            let values: Vec<_> = dates
+                .iter()
+                .map(|date| {
+                    while let Some((_, lines)) = stat.next_if(|(d, _)| d <= date) {
+                        value = lines;
+                    }
+                    value
+                })
+                .collect();
+            json!({ "name": name, "values": values })
+        })
+        .collect();
+
+    let data = json!({
+        "title": format!(
+            "{} for {}",
+            chart_title(options.chart, options.percentage),
+            repo_name(&options.repo_path)
+        ),
+        "column": name_column(options),
+        "percentage": options.percentage,
+        "dates": dates.iter().map(|date| date.format("%Y-%m-%d").to_string()).collect::<Vec<_>>(),
+        "series": series,
+        "markers": markers
+            .iter()
+            .map(|(name, time)| json!({ "name": name, "date": time.format("%Y-%m-%d").to_string() }))
+            .collect::<Vec<_>>(),
+        "owners": owners
+            .iter()
+            .map(|(name, lines)| json!({ "name": name, "lines": lines }))
+            .collect::<Vec<_>>(),
+    });
+
+    let mut out = BufWriter::new(File::create(&options.out_path)?);
+    out.write_all(render_page(&data)?.as_bytes())?;
+    out.flush()
+}
+
+/// Substitute the data for the placeholder in the page template
+fn render_page(data: &serde_json::Value) -> serde_json::Result<String> {
+    // A name like `</script>` must not end the script that embeds the data
+    let data = serde_json::to_string(data)?.replace("</", "<\\/");
+    Ok(TEMPLATE.replace(DATA_PLACEHOLDER, &data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_is_embedded_in_the_script() {
+        let data = json!({ "series": [{ "name": "</script><b>", "values": [1, 2] }] });
+        let page = render_page(&data).unwrap();
+        assert!(!page.contains(DATA_PLACEHOLDER));
+        assert_eq!(
+            page.matches("</script>").count(),
+            TEMPLATE.matches("</script>").count()
+        );
+
+        let embedded = r#"{"series":[{"name":"<\/script><b>","values":[1,2]}]}"#;
+        assert!(page.contains(embedded));
+        let parsed: serde_json::Value = serde_json::from_str(embedded).unwrap();
+        assert_eq!(parsed, data);
+    }
+}
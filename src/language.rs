@@ -0,0 +1,239 @@
+use super::report::print_columns;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// The language of the files that can't be detected
+const UNKNOWN_LANGUAGE: &str = "Unknown";
+
+const EXTENSIONS: &[(&str, &str)] = &[
+    ("c", "C"),
+    ("h", "C"),
+    ("cc", "C++"),
+    ("cpp", "C++"),
+    ("cxx", "C++"),
+    ("hh", "C++"),
+    ("hpp", "C++"),
+    ("cs", "C#"),
+    ("css", "CSS"),
+    ("go", "Go"),
+    ("html", "HTML"),
+    ("java", "Java"),
+    ("js", "JavaScript"),
+    ("jsx", "JavaScript"),
+    ("json", "JSON"),
+    ("kt", "Kotlin"),
+    ("lua", "Lua"),
+    ("md", "Markdown"),
+    ("pl", "Perl"),
+    ("php", "PHP"),
+    ("proto", "Protocol Buffers"),
+    ("py", "Python"),
+    ("rb", "Ruby"),
+    ("rs", "Rust"),
+    ("scala", "Scala"),
+    ("sh", "Shell"),
+    ("bash", "Shell"),
+    ("sql", "SQL"),
+    ("swift", "Swift"),
+    ("toml", "TOML"),
+    ("ts", "TypeScript"),
+    ("tsx", "TypeScript"),
+    ("yml", "YAML"),
+    ("yaml", "YAML"),
+];
+
+const FILE_NAMES: &[(&str, &str)] = &[
+    ("CMakeLists.txt", "CMake"),
+    ("Dockerfile", "Dockerfile"),
+    ("Makefile", "Makefile"),
+];
+
+const INTERPRETERS: &[(&str, &str)] = &[
+    ("bash", "Shell"),
+    ("node", "JavaScript"),
+    ("perl", "Perl"),
+    ("python", "Python"),
+    ("ruby", "Ruby"),
+    ("sh", "Shell"),
+    ("zsh", "Shell"),
+];
+
+/// Detect the language of the files from the extension, the file name or the shebang. The
+/// language of each path is only detected once.
+pub struct Languages {
+    overrides: BTreeMap<String, String>,
+    extensions: HashMap<String, String>,
+    file_names: HashMap<String, String>,
+    detected: RefCell<HashMap<PathBuf, String>>,
+}
+
+impl Languages {
+    /// Create the detector with the additional map from an extension, like `.tsx`, or a file
+    /// name, like `Jenkinsfile`, to the language, which overrides the builtin ones
+    pub fn new(overrides: &BTreeMap<String, String>) -> Self {
+        let to_map = |table: &[(&str, &str)]| {
+            table
+                .iter()
+                .map(|(key, lang)| (key.to_string(), lang.to_string()))
+                .collect::<HashMap<_, _>>()
+        };
+        let mut extensions = to_map(EXTENSIONS);
+        let mut file_names = to_map(FILE_NAMES);
+        for (key, lang) in overrides {
+            match key.strip_prefix('.') {
+                Some(ext) => extensions.insert(ext.to_lowercase(), lang.clone()),
+                None => file_names.insert(key.clone(), lang.clone()),
+            };
+        }
+        Self {
+            overrides: overrides.clone(),
+            extensions,
+            file_names,
+            detected: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// The language of the file, the first line is only read when the language can't be
+    /// detected from the path
+    pub fn detect<F: FnOnce() -> Option<String>>(&self, path: &Path, first_line: F) -> String {
+        if let Some(lang) = self.detected.borrow().get(path) {
+            return lang.clone();
+        }
+        let from_name = path
+            .file_name()
+            .and_then(|name| self.file_names.get(name.to_str()?))
+            .or_else(|| {
+                let ext = path.extension()?.to_str()?.to_lowercase();
+                self.extensions.get(&ext)
+            })
+            .cloned();
+        let lang = from_name
+            .or_else(|| first_line().as_deref().and_then(shebang_language))
+            .unwrap_or_else(|| UNKNOWN_LANGUAGE.to_string());
+        self.detected
+            .borrow_mut()
+            .insert(path.to_owned(), lang.clone());
+        lang
+    }
+}
+
+impl fmt::Debug for Languages {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Languages")
+            .field("overrides", &self.overrides)
+            .finish()
+    }
+}
+
+/// The language of the interpreter in a shebang like `#!/usr/bin/env python3`
+fn shebang_language(line: &str) -> Option<String> {
+    let mut words = line.strip_prefix("#!")?.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|word| !word.starts_with('-'))?;
+    }
+    let program = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    INTERPRETERS
+        .iter()
+        .find(|(name, _)| *name == program)
+        .map(|(_, lang)| lang.to_string())
+}
+
+fn rank(totals: HashMap<&str, usize>) -> Vec<(&str, usize)> {
+    let mut ranked: Vec<_> = totals.into_iter().filter(|(_, count)| *count > 0).collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    ranked
+}
+
+/// Print the lines owned by the top authors in each language, the languages with the most lines
+/// come first. The lines of the other authors are summed up in the Others row.
+pub fn print_language_report(matrix: &[(String, String, usize)], top: usize) {
+    let mut languages: HashMap<&str, usize> = HashMap::new();
+    let mut authors: HashMap<&str, usize> = HashMap::new();
+    let mut cells: HashMap<(&str, &str), usize> = HashMap::new();
+    for (author, lang, count) in matrix {
+        *languages.entry(lang).or_default() += count;
+        *authors.entry(author).or_default() += count;
+        *cells.entry((author, lang)).or_default() += count;
+    }
+
+    let languages = rank(languages);
+    let authors = rank(authors);
+
+    let mut header = vec!["Author".to_string()];
+    header.extend(languages.iter().map(|(lang, _)| lang.to_string()));
+    header.push("Total".to_string());
+    let mut rows = vec![header];
+
+    let row_of = |name: &str, members: &[(&str, usize)]| {
+        let mut row = vec![name.to_string()];
+        row.extend(languages.iter().map(|(lang, _)| {
+            let count: usize = members
+                .iter()
+                .map(|(author, _)| cells.get(&(*author, *lang)).unwrap_or(&0))
+                .sum();
+            count.to_string()
+        }));
+        row.push(members.iter().map(|(_, count)| count).sum::<usize>().to_string());
+        row
+    };
+
+    for author in authors.iter().take(top) {
+        rows.push(row_of(author.0, std::slice::from_ref(author)));
+    }
+    if authors.len() > top {
+        rows.push(row_of("Others", &authors[top..]));
+    }
+    rows.push(row_of("Total", &authors));
+
+    let mut right_aligned = vec![true; languages.len() + 2];
+    right_aligned[0] = false;
+    print_columns(&rows, &right_aligned);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn language_from_the_name_then_the_extension() {
+        let languages = Languages::new(&BTreeMap::new());
+        let detect = |path: &str| {
+            languages.detect(Path::new(path), || panic!("the first line isn't needed"))
+        };
+        assert_eq!(detect("src/main.rs"), "Rust");
+        assert_eq!(detect("include/a.HPP"), "C++");
+        assert_eq!(detect("build/Makefile"), "Makefile");
+        assert_eq!(detect("CMakeLists.txt"), "CMake");
+    }
+
+    #[test]
+    fn language_from_the_shebang() {
+        let languages = Languages::new(&BTreeMap::new());
+        let detect =
+            |path: &str, line: &str| languages.detect(Path::new(path), || Some(line.to_string()));
+        assert_eq!(detect("bin/run", "#!/usr/bin/env -S python3 -u"), "Python");
+        assert_eq!(detect("bin/build", "#!/bin/bash -e"), "Shell");
+        assert_eq!(detect("bin/tool", "#!/usr/bin/tclsh"), UNKNOWN_LANGUAGE);
+        assert_eq!(detect("README", "No shebang"), UNKNOWN_LANGUAGE);
+        // The detected language is remembered
+        assert_eq!(detect("bin/run", "#!/bin/sh"), "Python");
+    }
+
+    #[test]
+    fn overrides_replace_the_builtin_languages() {
+        let overrides: BTreeMap<_, _> = vec![
+            (".h".to_string(), "C++".to_string()),
+            ("Jenkinsfile".to_string(), "Groovy".to_string()),
+        ]
+        .into_iter()
+        .collect();
+        let languages = Languages::new(&overrides);
+        let detect = |path: &str| languages.detect(Path::new(path), || None);
+        assert_eq!(detect("a.h"), "C++");
+        assert_eq!(detect("ci/Jenkinsfile"), "Groovy");
+        assert_eq!(detect("a.c"), "C");
+    }
+}
@@ -0,0 +1,11 @@
+//! The ownership engine behind constat.
+//!
+//! This crate walks the history of a git repository and tracks which author
+//! owns each line of every file, so that callers can compute statistics of
+//! the code owned by each contributor over time.
+pub mod analyzer;
+
+pub use analyzer::{
+    run_stat, ConstatError, GitCommit, GitRepo, HistoryGraph, Samples, StatCache, StatOptions,
+    Tree,
+};
@@ -1,31 +1,266 @@
-mod analyzer;
+mod churn;
+mod config;
+mod export;
+mod filter;
+mod html;
+mod language;
 mod options;
 mod plotting;
+mod report;
+mod survival;
+mod teams;
+mod terminal;
 
-use plotting::render_plot;
-use options::ConstatOptions;
-use std::collections::{BTreeMap, HashMap};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use constat::analyzer;
+use plotting::{render_animation, render_grid, render_plot, render_survival, AuthorInfo};
+use options::{Bucket, ChartKind, ConstatOptions, OutputFormat, Period};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::path::Path;
+use churn::ChurnRecord;
+use survival::{LineBirth, LineDeath};
+
+/// The lines owned by each author at a commit, which is stored in the cache
+type CommitRecord = (i64, Vec<(String, usize)>);
+
+/// The stat of the last commit in each bucket, keyed by the bucket
+type Snapshots = HashMap<String, CommitRecord>;
+
+fn record_stat(snapshots: &mut Snapshots, bucket: String, timestamp: i64, stat: &[(String, usize)]) {
+    let cell = snapshots.entry(bucket).or_insert((timestamp, vec![]));
+    if cell.0 <= timestamp {
+        *cell = (timestamp, stat.to_vec());
+    }
+}
+
+/// Turn the snapshots into the time series of each author, every bucket is placed at the date
+/// of its last commit
+fn collect_author_info(snapshots: Snapshots) -> AuthorInfo {
+    let mut snapshots: Vec<_> = snapshots.into_values().collect();
+    snapshots.sort_by_key(|(timestamp, _)| *timestamp);
+
+    let mut author_info: AuthorInfo = HashMap::new();
+    for (timestamp, stat) in snapshots {
+        let date = Utc.timestamp_opt(timestamp, 0).unwrap().date();
+        // The authors missing in the snapshot don't own any lines at that time
+        for stats in author_info.values_mut() {
+            stats.insert(date, 0);
+        }
+        // The same name may be used by multiple identities
+        for (name, count) in stat {
+            *author_info.entry(name).or_default().entry(date).or_default() += count;
+        }
+    }
+    author_info
+}
+
+/// The directory at the depth that contains the file, or the parent directory of the file if it's
+/// above the depth
+fn dir_at_depth(path: &Path, depth: usize) -> String {
+    let dir: Vec<_> = path
+        .parent()
+        .into_iter()
+        .flat_map(|parent| parent.iter())
+        .take(depth)
+        .map(|part| part.to_string_lossy())
+        .collect();
+    if dir.is_empty() {
+        ".".to_string()
+    } else {
+        dir.join("/")
+    }
+}
+
+/// The time of the root commit on the first-parent chain of the analyzed revision, None if it
+/// can't be found. The errors are left to the analysis, which reports them.
+fn first_commit_time(options: &ConstatOptions) -> Option<DateTime<Utc>> {
+    let repo = analyzer::GitRepo::open(&options.repo_path).ok()?;
+    let (head, _) = repo
+        .find_range(options.revision.as_deref().unwrap_or("HEAD"))
+        .ok()?;
+    let time = head.first_parent_chain().last()?.get_timestamp();
+    time
+}
+
+/// The end of every bucket, so that the stat only runs on the last commit of each bucket. None if
+/// the stat of every commit is needed.
+fn bucket_ends(options: &ConstatOptions, tag_times: &[i64]) -> Option<Vec<DateTime<Utc>>> {
+    if !options.plots_history() {
+        return None;
+    }
+    match options.bucket {
+        Bucket::Period(Period::Day) => None,
+        Bucket::Period(period) => {
+            // The buckets before the first commit don't sample anything
+            let mut start = match first_commit_time(options) {
+                Some(time) => time.date_naive(),
+                None => return Some(vec![]),
+            };
+            let today = Utc::now().date_naive();
+            let mut ends = vec![];
+            while start <= today {
+                start = period.next_start(start);
+                ends.push(Utc.from_utc_datetime(&start.and_hms_opt(0, 0, 0).unwrap()) - Duration::seconds(1));
+            }
+            Some(ends)
+        }
+        Bucket::Tag => Some(
+            tag_times
+                .iter()
+                .map(|&time| Utc.timestamp_opt(time, 0).unwrap())
+                .collect(),
+        ),
+    }
+}
+
+/// Load the payload cached along with the tree of the commit, None if the commit isn't cached.
+/// The payload of every cached commit is checked by `check_payload` before the analysis.
+fn load_payload<T: DeserializeOwned>(
+    cache: Option<&analyzer::StatCache>,
+    repo: &analyzer::GitRepo,
+    commit: &analyzer::GitCommit,
+) -> Option<T> {
+    let cache = cache.filter(|cache| cache.contains(repo, commit))?;
+    let payload = cache.load_payload(repo, commit).ok()??;
+    serde_json::from_slice(&payload).ok()
+}
+
+/// Check if the payload cached along with the tree of the commit can be loaded. Otherwise, the
+/// entry is dropped, so that the history before the commit is analyzed instead of being skipped.
+/// The program only exits if the entry can't be removed.
+fn check_payload<T: DeserializeOwned>(
+    cache: Option<&analyzer::StatCache>,
+    repo: &analyzer::GitRepo,
+    commit: &analyzer::GitCommit,
+) {
+    let cache = match cache {
+        Some(cache) if cache.contains(repo, commit) => cache,
+        _ => return,
+    };
+    let result = match cache.load_payload(repo, commit) {
+        Ok(Some(payload)) => serde_json::from_slice::<T>(&payload)
+            .map(|_| ())
+            .map_err(|err| err.to_string()),
+        Ok(None) => Err("The payload is missing".to_string()),
+        Err(err) => Err(err.to_string()),
+    };
+    if let Err(err) = result {
+        let id = commit.id().unwrap_or_else(git2::Oid::zero);
+        eprintln!("Warning: Dropped the cached stats of commit {}: {}", id, err);
+        if let Err(err) = cache.remove(repo, commit) {
+            eprintln!("Error: Cannot remove the cache entry: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// List the tags matching the pattern, the program exits if the repository can't be opened
+fn list_tags(options: &ConstatOptions, pattern: Option<&str>) -> Vec<(String, DateTime<Utc>)> {
+    match analyzer::GitRepo::open(&options.repo_path).and_then(|repo| repo.tags(pattern)) {
+        Ok(tags) => tags,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
 
 fn main() {
     let options = ConstatOptions::new();
 
-    let mut author_info: HashMap<_, BTreeMap<_, usize>> = HashMap::new();
+    let mut snapshots: Snapshots = HashMap::new();
+
+    let mut final_ownership = vec![];
+
+    let mut language_matrix = vec![];
+
+    let mut records: Vec<CommitRecord> = vec![];
+
+    // The snapshots and records of each directory in a grid chart
+    let mut panels: HashMap<String, Snapshots> = HashMap::new();
+    let mut panel_records: Vec<(String, CommitRecord)> = vec![];
+
+    // The lines removed by each analyzed commit, keyed by the commit id
+    let mut removals: HashMap<String, Vec<LineDeath>> = HashMap::new();
+    let mut line_births: Vec<LineBirth> = vec![];
+    let mut end_time = 0;
+
+    // The churn of each analyzed commit, keyed by the commit id
+    let mut churn: HashMap<String, Vec<ChurnRecord>> = HashMap::new();
+
+    let mut author_emails = HashMap::new();
 
     let mut pb = None;
 
     let quiet = options.quiet || options.verbose;
 
-    analyzer::run_stat(
+    let predict = |f: &std::path::Path| {
+        options.patterns.iter().any(|p| p.matches_path(f))
+            && options.path_filter.matches(f)
+    };
+
+    let tag_times: Vec<_> = if options.bucket == Bucket::Tag {
+        list_tags(&options, None)
+            .into_iter()
+            .map(|(_, time)| time.timestamp())
+            .collect()
+    } else {
+        vec![]
+    };
+
+    let bucket_of = |timestamp: i64| match options.bucket {
+        Bucket::Period(period) => period.label(timestamp),
+        Bucket::Tag => tag_times.partition_point(|&time| time < timestamp).to_string(),
+    };
+
+    let stat_options = analyzer::StatOptions {
+        verbose: options.verbose,
+        mailmap: options.mailmap.clone(),
+        aliases: options.aliases.clone(),
+        co_author_credit: options.co_author_credit,
+        revision: options.revision.clone(),
+        until: options.until,
+        cache: options
+            .cache_dir
+            .as_ref()
+            .map(|dir| analyzer::StatCache::new(dir, &options.cache_key())),
+        samples: bucket_ends(&options, &tag_times).map(analyzer::Samples::Dates),
+    };
+
+    let result = analyzer::run_stat(
         &options.repo_path,
-        options.verbose,
+        &stat_options,
         |commit| {
+            // The payload type is the one stored at the end of the analysis below
+            let cache = stat_options.cache.as_ref();
+            if options.survival {
+                check_payload::<HashMap<String, Vec<LineDeath>>>(cache, commit.repo(), commit);
+            } else if options.churn.is_some() {
+                check_payload::<HashMap<String, Vec<ChurnRecord>>>(cache, commit.repo(), commit);
+            } else if options.split_depth.is_some() {
+                check_payload::<Vec<(String, CommitRecord)>>(cache, commit.repo(), commit);
+            } else {
+                check_payload::<Vec<CommitRecord>>(cache, commit.repo(), commit);
+            }
+
             let time = commit.get_timestamp();
             time.map_or(true, |ts| {
                 options.since.map_or(true, |since| ts.date() >= since)
             })
         },
-        |repo, commit, tree, _proc, total| {
-            let date = commit.get_timestamp().unwrap().date();
+        |repo, commit, tree, step, total| {
+            let language_of = |f: &std::path::Path| options.languages.detect(f, || commit.first_line(f));
+            let commit_tree = commit.tree();
+            let predict = |f: &std::path::Path| {
+                predict(f)
+                    && (options.include_generated
+                        || !commit_tree.as_ref().map_or(false, |t| commit.is_generated(t, f)))
+                    && options
+                        .language
+                        .as_deref()
+                        .map_or(true, |lang| language_of(f).eq_ignore_ascii_case(lang))
+            };
 
             if !quiet {
                 if pb.is_none() {
@@ -35,24 +270,306 @@ fn main() {
                 pb.as_ref().unwrap().inc(1);
             }
 
-            for (author_id, count) in tree
-                .stat(|f| options.patterns.iter().any(|p| p.matches_path(f)))
-                .into_iter()
-                .enumerate()
+            if options.report || options.bus_factor {
+                if step + 1 == total {
+                    final_ownership = tree
+                        .files()
+                        .filter(|f| predict(f))
+                        .map(|f| {
+                            let owners = tree
+                                .file_stat(f, repo)
+                                .unwrap()
+                                .into_iter()
+                                .map(|(id, count)| {
+                                    (repo.query_author_name(id).unwrap(), count as usize)
+                                })
+                                .filter(|(name, _)| !options.exclude_older || name != "Older Code")
+                                .collect();
+                            (f.to_owned(), owners)
+                        })
+                        .collect();
+                    final_ownership.sort_by(|a: &report::FileOwnership, b| a.0.cmp(&b.0));
+                }
+            } else if options.language_report {
+                if step + 1 == total {
+                    language_matrix = tree
+                        .stat_by(predict, language_of, repo)
+                        .into_iter()
+                        .flat_map(|(lang, stat)| {
+                            stat.into_iter()
+                                .map(move |(id, count)| {
+                                    (repo.query_author_name(id).unwrap(), lang.clone(), count as usize)
+                                })
+                        })
+                        .filter(|(name, _, _)| !options.exclude_older || name != "Older Code")
+                        .collect();
+                }
+            } else if options.survival {
+                let time = commit.get_timestamp().unwrap().timestamp();
+                let credit = |((author, birth), count): ((u32, i64), u32)| {
+                    repo.credit_lines(author, count, birth as u64)
+                        .into_iter()
+                        .map(move |(id, count)| (repo.query_author_name(id).unwrap(), birth, count as usize))
+                        .filter(|(name, _, _)| !options.exclude_older || name != "Older Code")
+                };
+                // The cached payload carries the lines removed by all the commits before
+                if let Some(cached) = load_payload::<HashMap<String, Vec<LineDeath>>>(
+                    stat_options.cache.as_ref(),
+                    repo,
+                    commit,
+                ) {
+                    removals.extend(cached);
+                } else {
+                    let removed = tree
+                        .removed_birth_stat(predict)
+                        .into_iter()
+                        .flat_map(credit)
+                        .map(|(name, birth, count)| (name, birth, time, count))
+                        .collect();
+                    removals.insert(commit.id().unwrap().to_string(), removed);
+                }
+
+                if step + 1 == total {
+                    line_births = tree.birth_stat(predict).into_iter().flat_map(credit).collect();
+                    end_time = time;
+                }
+            } else if options.churn.is_some() {
+                // The cached payload carries the churn of all the commits before
+                if let Some(cached) = load_payload::<HashMap<String, Vec<ChurnRecord>>>(
+                    stat_options.cache.as_ref(),
+                    repo,
+                    commit,
+                ) {
+                    churn.extend(cached);
+                } else {
+                    let time = commit.get_timestamp().unwrap().timestamp();
+                    let author = commit.author_id();
+                    let deleted = tree.removed_birth_stat(predict).values().sum();
+                    let (mut own, mut others) = (0, 0);
+                    for ((owner, _), count) in tree.rewritten_birth_stat(predict) {
+                        if owner == author {
+                            own += count;
+                        } else {
+                            others += count;
+                        }
+                    }
+                    let records = repo
+                        .credit_lines(author, tree.added_stat(predict), time as u64)
+                        .into_iter()
+                        .zip(repo.credit_lines(author, deleted, time as u64))
+                        .zip(repo.credit_lines(author, own, time as u64))
+                        .zip(repo.credit_lines(author, others, time as u64))
+                        .map(|((((id, added), (_, deleted)), (_, own)), (_, others))| {
+                            let name = repo.query_author_name(id).unwrap();
+                            let lines = (added as usize, deleted as usize);
+                            (name, time, lines.0, lines.1, own as usize, others as usize)
+                        })
+                        .filter(|(name, _, added, deleted, _, _)| {
+                            added + deleted > 0
+                                && (!options.exclude_older || name != "Older Code")
+                        })
+                        .collect();
+                    churn.insert(commit.id().unwrap().to_string(), records);
+                }
+            } else if let Some(cached) = options.split_depth.and_then(|_| {
+                load_payload::<Vec<(String, CommitRecord)>>(stat_options.cache.as_ref(), repo, commit)
+            }) {
+                for (dir, (timestamp, stat)) in cached {
+                    let snapshots = panels.entry(dir.clone()).or_default();
+                    record_stat(snapshots, bucket_of(timestamp), timestamp, &stat);
+                    panel_records.push((dir, (timestamp, stat)));
+                }
+            } else if let Some(cached) =
+                load_payload::<Vec<CommitRecord>>(stat_options.cache.as_ref(), repo, commit)
             {
-                let cell = author_info
-                    .entry(repo.query_author_name(author_id as u32).unwrap())
-                    .or_insert_with(|| BTreeMap::new())
-                    .entry(date)
-                    .or_default();
-                *cell = (*cell).max(count as usize);
+                for (timestamp, stat) in cached {
+                    record_stat(&mut snapshots, bucket_of(timestamp), timestamp, &stat);
+                    records.push((timestamp, stat));
+                }
+            } else {
+                let time = commit.get_timestamp().unwrap().timestamp();
+                let stat_of = |predict: &dyn Fn(&Path) -> bool| -> Vec<(String, usize)> {
+                    match options.chart {
+                        ChartKind::Author => tree
+                            .stat(predict, repo)
+                            .into_iter()
+                            .map(|(id, count)| (repo.query_author_name(id).unwrap(), count as usize))
+                            .collect(),
+                        ChartKind::Cohort(period) => tree
+                            .cohort_stat(predict, |time| period.label(time))
+                            .into_iter()
+                            .map(|(label, count)| (label, count as usize))
+                            .collect(),
+                        ChartKind::Language => tree
+                            .lines_by(predict, language_of)
+                            .into_iter()
+                            .map(|(lang, count)| (lang, count as usize))
+                            .collect(),
+                    }
+                };
+                if let Some(depth) = options.split_depth {
+                    // A removed directory is still recorded, so that its lines drop to zero
+                    let mut dirs: Vec<_> = tree
+                        .files()
+                        .filter(|f| predict(f))
+                        .map(|f| dir_at_depth(f, depth))
+                        .chain(panels.keys().cloned())
+                        .collect();
+                    dirs.sort();
+                    dirs.dedup();
+                    for dir in dirs {
+                        let stat = stat_of(&|f| predict(f) && dir_at_depth(f, depth) == dir);
+                        let snapshots = panels.entry(dir.clone()).or_default();
+                        record_stat(snapshots, bucket_of(time), time, &stat);
+                        if stat_options.cache.is_some() {
+                            panel_records.push((dir, (time, stat)));
+                        }
+                    }
+                } else {
+                    let stat = stat_of(&predict);
+                    record_stat(&mut snapshots, bucket_of(time), time, &stat);
+                    if stat_options.cache.is_some() {
+                        records.push((time, stat));
+                    }
+                }
+            }
+
+            if step + 1 == total {
+                if options.teams.is_some() {
+                    author_emails = repo.author_emails();
+                }
+                if let Some(cache) = stat_options.cache.as_ref() {
+                    let payload = if options.survival {
+                        serde_json::to_vec(&removals).unwrap()
+                    } else if options.churn.is_some() {
+                        serde_json::to_vec(&churn).unwrap()
+                    } else if options.split_depth.is_some() {
+                        serde_json::to_vec(&panel_records).unwrap()
+                    } else {
+                        serde_json::to_vec(&records).unwrap()
+                    };
+                    if let Err(err) = cache.store(repo, commit, tree, &payload) {
+                        eprintln!("Warning: {}", err);
+                    }
+                }
             }
         },
     );
 
-    render_plot(&mut author_info, &options);
+    if let Err(err) = result {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    }
+
+    if let Some(pb) = pb {
+        pb.finish_and_clear();
+    }
+
+    if options.survival {
+        let line_deaths: Vec<_> = removals.into_values().flatten().collect();
+        let (all, authors) = survival::analyze_survival(&line_deaths, &line_births, end_time);
+        survival::print_survival_report(&all, &authors, options.top);
+        if options.survival_chart {
+            let curves: Vec<_> = std::iter::once(("All", &all))
+                .chain(authors.iter().take(options.top).map(|(name, s)| (name.as_str(), s)))
+                .collect();
+            render_survival(&curves, &options);
+            if options.open {
+                open::that(options.out_path).ok();
+            }
+        }
+        return;
+    }
+
+    if let Some(period) = options.churn {
+        let records: Vec<_> = churn.into_values().flatten().collect();
+        churn::print_churn_report(&records, period);
+        return;
+    }
+
+    if options.language_report {
+        language::print_language_report(&language_matrix, options.top);
+        return;
+    }
+
+    if options.report || options.bus_factor {
+        if options.report {
+            report::print_ownership_report(&final_ownership, options.report_depth, options.top);
+        }
+        if options.report && options.bus_factor {
+            println!();
+        }
+        if options.bus_factor {
+            report::print_bus_factor_report(
+                &final_ownership,
+                options.report_depth,
+                options.concentration_threshold,
+            );
+        }
+        return;
+    }
+
+    // The tags marked on the charts
+    let markers = || {
+        options
+            .tag_pattern
+            .as_deref()
+            .map_or_else(Vec::new, |pattern| list_tags(&options, Some(pattern)))
+    };
+
+    if options.split_depth.is_some() {
+        let panels = panels
+            .into_iter()
+            .map(|(dir, snapshots)| {
+                let author_info = collect_author_info(snapshots);
+                match (&options.teams, options.chart) {
+                    (Some(teams), ChartKind::Author) => {
+                        (dir, teams.aggregate(author_info, &author_emails))
+                    }
+                    _ => (dir, author_info),
+                }
+            })
+            .collect();
+        render_grid(panels, markers(), &options);
+        if options.open {
+            open::that(options.out_path).ok();
+        }
+        return;
+    }
+
+    let mut author_info = collect_author_info(snapshots);
+
+    if let (Some(teams), ChartKind::Author) = (&options.teams, options.chart) {
+        author_info = teams.aggregate(author_info, &author_emails);
+    }
+
+    let result = match options.format {
+        OutputFormat::Csv => export::write_csv(&author_info, &options.out_path),
+        OutputFormat::Json => export::write_json(&author_info, &options.out_path),
+        OutputFormat::Html => {
+            html::write_html(&mut author_info, &markers(), &options)
+        }
+        OutputFormat::Term => {
+            terminal::print_term(&mut author_info, &markers(), &options);
+            Ok(())
+        }
+        OutputFormat::Gif => {
+            render_animation(&author_info, &markers(), &options);
+            Ok(())
+        }
+        OutputFormat::Bitmap | OutputFormat::Svg => {
+            render_plot(&mut author_info, markers(), &options);
+            Ok(())
+        }
+    };
+
+    if let Err(err) = result {
+        eprintln!("Error: Cannot write {}: {}", options.out_path.display(), err);
+        std::process::exit(1);
+    }
 
-    if options.open {
+    // Nothing is written to open in the terminal mode
+    if options.open && options.format != OutputFormat::Term {
         open::that(options.out_path).ok();
     }
 }
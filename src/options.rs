@@ -1,21 +1,124 @@
-use chrono::{Date, NaiveDate, TimeZone, Utc};
-use clap::{load_yaml, value_t_or_exit, values_t_or_exit, App, ArgMatches};
+use chrono::{Date, DateTime, Datelike, Duration, NaiveDate, TimeZone, Utc};
+use clap::{load_yaml, value_t_or_exit, values_t_or_exit, App, ArgMatches, Error};
 use glob::Pattern;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use constat::analyzer::CoAuthorCredit;
+use super::config::load_file;
+use super::filter::PathFilter;
+use super::language::Languages;
+use super::teams::Teams;
 use std::path::{Path, PathBuf};
 use tempfile::{tempdir, TempDir};
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OutputFormat {
+    Bitmap,
+    Svg,
+    Csv,
+    Json,
+    Html,
+    Term,
+    Gif,
+}
+
+/// The period that the stats are grouped by
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Period {
+    Day,
+    Week,
+    Month,
+    Quarter,
+    Year,
+}
+
+impl Period {
+    /// The label of the period that contains the time, which sorts in chronological order
+    pub fn label(self, timestamp: i64) -> String {
+        let time = Utc.timestamp_opt(timestamp, 0).unwrap();
+        match self {
+            Period::Day => time.format("%Y-%m-%d").to_string(),
+            Period::Week => {
+                let week = time.iso_week();
+                format!("{}-W{:02}", week.year(), week.week())
+            }
+            Period::Month => format!("{}-{:02}", time.year(), time.month()),
+            Period::Quarter => format!("{}Q{}", time.year(), time.month0() / 3 + 1),
+            Period::Year => format!("{}", time.year()),
+        }
+    }
+
+    /// The first day of the period after the one that contains the date
+    pub fn next_start(self, date: NaiveDate) -> NaiveDate {
+        let month_start = |year: i32, month0: u32| {
+            NaiveDate::from_ymd_opt(year + (month0 / 12) as i32, month0 % 12 + 1, 1).unwrap()
+        };
+        match self {
+            Period::Day => date.succ_opt().unwrap(),
+            Period::Week => {
+                date + Duration::days(7 - date.weekday().num_days_from_monday() as i64)
+            }
+            Period::Month => month_start(date.year(), date.month0() + 1),
+            Period::Quarter => month_start(date.year(), date.month0() / 3 * 3 + 3),
+            Period::Year => month_start(date.year() + 1, 0),
+        }
+    }
+}
+
+/// How the commits are grouped in the time series, only the last commit of each bucket is sampled
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Bucket {
+    Period(Period),
+    /// The commits from a tag, exclusive, to the next tag, inclusive
+    Tag,
+}
+
+/// What the stack of the chart is grouped by
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ChartKind {
+    Author,
+    Cohort(Period),
+    Language,
+}
+
 pub struct ConstatOptions {
     pub repo_path: PathBuf,
     pub top: usize,
     pub out_path: PathBuf,
+    pub format: OutputFormat,
     pub resolution: (u32, u32),
+    pub frame_delay: u32,
     pub patterns: Vec<Pattern>,
+    pub path_filter: PathFilter,
+    pub include_generated: bool,
+    pub language: Option<String>,
+    pub languages: Languages,
     pub top_only: bool,
+    pub percentage: bool,
+    pub chart: ChartKind,
+    pub bucket: Bucket,
+    pub split_depth: Option<usize>,
+    pub tag_pattern: Option<String>,
     pub open: bool,
     pub since: Option<Date<Utc>>,
+    pub until: Option<DateTime<Utc>>,
     pub exclude_older: bool,
     pub quiet: bool,
     pub verbose: bool,
     pub pinned_author: Vec<Pattern>,
+    pub teams: Option<Teams>,
+    pub mailmap: Option<PathBuf>,
+    pub aliases: Vec<(String, String)>,
+    pub report: bool,
+    pub report_depth: Option<usize>,
+    pub bus_factor: bool,
+    pub language_report: bool,
+    pub concentration_threshold: f64,
+    pub survival: bool,
+    pub survival_chart: bool,
+    pub churn: Option<Period>,
+    pub co_author_credit: CoAuthorCredit,
+    pub cache_dir: Option<PathBuf>,
+    pub revision: Option<String>,
     _temp_file_handle: Option<TempDir>,
 }
 
@@ -25,9 +128,12 @@ impl ConstatOptions {
         let options = App::from_yaml(option_spec).get_matches();
 
         let (repo_path, handle) = get_repo_path(&options);
-        let out_path = get_out_path(&options, repo_path.as_ref());
+        let config = load_config(&options, repo_path.as_ref());
+        let format = get_format(&options, &config);
+        let out_path = get_out_path(&options, repo_path.as_ref(), format);
 
-        let patterns = parse_patterns(&options);
+        let patterns = parse_patterns(&options, &config);
+        let path_filter = get_path_filter(&options, &config);
         let pinned_author = options.value_of("keep-author").map_or_else(
             || vec![],
             |author| author.split(",").map(|p| p.parse().unwrap()).collect(),
@@ -36,23 +142,110 @@ impl ConstatOptions {
         Self {
             verbose: options.is_present("verbose"),
             repo_path,
-            top: get_num_tops(&options),
+            top: get_num_tops(&options, &config),
             out_path,
-            resolution: get_resolution(&options),
+            format,
+            resolution: get_resolution(&options, &config),
+            frame_delay: if options.is_present("frame-delay") {
+                value_t_or_exit!(options, "frame-delay", u32)
+            } else {
+                500
+            },
             patterns,
+            path_filter,
+            include_generated: options.is_present("include-generated"),
+            language: options.value_of("language").map(ToOwned::to_owned),
+            languages: Languages::new(&config.languages),
             top_only: options.is_present("top-only"),
+            percentage: options.is_present("percentage"),
+            chart: get_chart_kind(&options),
+            bucket: get_bucket(&options, &config),
+            split_depth: get_split_depth(&options, format),
+            tag_pattern: if options.is_present("tags") {
+                Some(options.value_of("tags").unwrap_or("*").to_string())
+            } else {
+                None
+            },
             open: options.is_present("open"),
             pinned_author,
+            teams: options.value_of("teams").map(|path| {
+                Teams::load(path).unwrap_or_else(|err| {
+                    eprintln!("Error: {}", err);
+                    std::process::exit(1);
+                })
+            }),
             since: if options.is_present("since-date") {
                 Some(parse_date(&options, "since-date"))
             } else {
                 None
             },
+            until: if options.is_present("until-date") {
+                Some(parse_end_of_day(&options, "until-date"))
+            } else {
+                None
+            },
             exclude_older: options.is_present("exclude-older"),
             quiet: options.is_present("quiet"),
+            mailmap: options.value_of("mailmap").map(PathBuf::from),
+            aliases: get_aliases(&config),
+            co_author_credit: get_co_author_credit(&options),
+            report: options.is_present("report"),
+            report_depth: if options.is_present("report-depth") {
+                Some(value_t_or_exit!(options, "report-depth", usize))
+            } else {
+                None
+            },
+            bus_factor: options.is_present("bus-factor"),
+            language_report: options.is_present("language-report"),
+            concentration_threshold: get_concentration_threshold(&options),
+            survival: options.is_present("survival") || options.is_present("survival-chart"),
+            survival_chart: options.is_present("survival-chart"),
+            churn: get_churn_period(&options),
+            revision: options.value_of("revision").map(ToOwned::to_owned),
+            cache_dir: options.value_of("cache").map(PathBuf::from),
             _temp_file_handle: handle,
         }
     }
+
+    /// The key of the cache entries, which covers all the options that affect the cached stats.
+    /// The bucket decides which commits are sampled into the payload, and the older code is
+    /// filtered out of the survival and churn records before they are cached.
+    /// The analyzed revision isn't a part of the key, since only the start of a range affects
+    /// the history before a cached commit.
+    pub fn cache_key(&self) -> String {
+        let range_start = self
+            .revision
+            .as_deref()
+            .filter(|rev| rev.contains(".."))
+            .and_then(|rev| rev.split("..").next());
+        format!(
+            "{:?}|{:?}|{}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{}|{:?}|{}|{}|{}|{}",
+            self.patterns,
+            self.path_filter,
+            self.include_generated,
+            self.language,
+            self.languages,
+            self.since,
+            self.chart,
+            self.split_depth,
+            self.bucket,
+            self.exclude_older,
+            range_start,
+            self.report || self.bus_factor,
+            self.language_report,
+            self.survival,
+            self.churn.is_some()
+        )
+    }
+
+    /// Whether the stats of every commit are collected for the chart and the exported time series
+    pub fn plots_history(&self) -> bool {
+        !(self.report
+            || self.bus_factor
+            || self.language_report
+            || self.survival
+            || self.churn.is_some())
+    }
 }
 #[allow(dead_code)]
 fn parse_date(parsed: &ArgMatches, name: &str) -> Date<Utc> {
@@ -60,11 +253,106 @@ fn parse_date(parsed: &ArgMatches, name: &str) -> Date<Utc> {
     Utc.from_utc_date(&nd)
 }
 
-fn parse_patterns(parsed: &ArgMatches) -> Vec<Pattern> {
-    if !parsed.is_present("file-patterns") {
-        return vec!["**/*".parse().unwrap()];
+fn parse_end_of_day(parsed: &ArgMatches, name: &str) -> DateTime<Utc> {
+    let nd = value_t_or_exit!(parsed.value_of(name), NaiveDate);
+    Utc.from_utc_datetime(&nd.and_hms_opt(23, 59, 59).unwrap())
+}
+
+fn parse_patterns(parsed: &ArgMatches, config: &ConfigFile) -> Vec<Pattern> {
+    if parsed.is_present("file-patterns") {
+        return values_t_or_exit!(parsed.values_of("file-patterns"), Pattern);
     }
-    values_t_or_exit!(parsed.values_of("file-patterns"), Pattern)
+    match config.patterns.as_ref() {
+        Some(patterns) => parse_globs(patterns),
+        None => vec!["**/*".parse().unwrap()],
+    }
+}
+
+/// Parse the glob patterns of the configuration file, which are checked by `check_config`
+fn parse_globs(patterns: &[String]) -> Vec<Pattern> {
+    patterns.iter().map(|p| p.parse().unwrap()).collect()
+}
+
+/// The options in the configuration file, the command line options override them
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    patterns: Option<Vec<String>>,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    /// The canonical name of the author to the emails or `Name <email>` identities
+    aliases: BTreeMap<String, Vec<String>>,
+    top: Option<usize>,
+    resolution: Option<String>,
+    bucket: Option<String>,
+    format: Option<String>,
+    /// The extension, like `.tsx`, or the file name to the language
+    languages: BTreeMap<String, String>,
+}
+
+/// Load the configuration file given by `--config`, or the `.constat.toml` in the repository if
+/// there's one. The program exits if the file is invalid.
+fn load_config(parsed: &ArgMatches, repo_path: &Path) -> ConfigFile {
+    let path = match parsed.value_of("config") {
+        Some(path) => PathBuf::from(path),
+        None if repo_path.join(".constat.toml").is_file() => repo_path.join(".constat.toml"),
+        None => return ConfigFile::default(),
+    };
+    let config = load_file(&path).and_then(|config| {
+        check_config(&config)
+            .map(|_| config)
+            .map_err(|err| format!("Invalid configuration {}: {}", path.display(), err))
+    });
+    config.unwrap_or_else(|err| {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    })
+}
+
+fn check_config(config: &ConfigFile) -> Result<(), String> {
+    let one_of = |key: &str, value: &Option<String>, choices: &[&str]| match value {
+        Some(value) if !choices.contains(&value.as_str()) => {
+            Err(format!("{} must be one of {}", key, choices.join(", ")))
+        }
+        _ => Ok(()),
+    };
+    one_of("bucket", &config.bucket, &["day", "week", "month", "quarter", "tag"])?;
+    one_of("format", &config.format, &["png", "svg", "csv", "json", "html", "term", "gif"])?;
+
+    if let Some(pattern) = config.patterns.iter().flatten().find(|p| p.parse::<Pattern>().is_err()) {
+        return Err(format!("{} is not a valid glob pattern", pattern));
+    }
+    if config.top == Some(0) {
+        return Err("top must be a positive integer".to_string());
+    }
+    if config.resolution.as_deref().map_or(false, |res| parse_resolution(res).is_none()) {
+        return Err("resolution must be in format <width>x<height>".to_string());
+    }
+    Ok(())
+}
+
+/// The include and exclude patterns, the patterns of the configuration file are used only if
+/// they are not given in the command line. The program exits if a pattern is invalid.
+fn get_path_filter(parsed: &ArgMatches, config: &ConfigFile) -> PathFilter {
+    let patterns = |name: &str, default: &[String]| match parsed.values_of(name) {
+        Some(values) => values.map(ToOwned::to_owned).collect(),
+        None => default.to_vec(),
+    };
+    PathFilter::new(patterns("include", &config.include), patterns("exclude", &config.exclude))
+        .unwrap_or_else(|err| {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        })
+}
+
+/// The author aliases of the configuration file, each entry maps an email or a `Name <email>`
+/// identity to the canonical name
+fn get_aliases(config: &ConfigFile) -> Vec<(String, String)> {
+    config
+        .aliases
+        .iter()
+        .flat_map(|(name, ids)| ids.iter().map(move |id| (name.clone(), id.clone())))
+        .collect()
 }
 
 fn get_repo_path(parsed: &ArgMatches) -> (PathBuf, Option<TempDir>) {
@@ -90,33 +378,228 @@ fn get_repo_path(parsed: &ArgMatches) -> (PathBuf, Option<TempDir>) {
     (path, Some(temp))
 }
 
-fn get_num_tops(parsed: &ArgMatches) -> usize {
-    parsed.value_of("top").unwrap_or("5").parse().unwrap()
+fn get_co_author_credit(parsed: &ArgMatches) -> CoAuthorCredit {
+    match parsed.value_of("co-authors") {
+        Some("split") => CoAuthorCredit::Split,
+        Some("duplicate") => CoAuthorCredit::Duplicate,
+        _ => CoAuthorCredit::Ignore,
+    }
+}
+
+/// The percentage of lines owned by a single author that makes a file concentrated, the program
+/// exits if it's not a number between 0 and 100
+fn get_concentration_threshold(parsed: &ArgMatches) -> f64 {
+    if !parsed.is_present("concentration-threshold") {
+        return 80.0;
+    }
+    let threshold = value_t_or_exit!(parsed, "concentration-threshold", f64);
+    if !(0.0..=100.0).contains(&threshold) {
+        Error::value_validation_auto(format!(
+            "The concentration threshold {} isn't between 0 and 100",
+            threshold
+        ))
+        .exit();
+    }
+    threshold
+}
+
+fn get_chart_kind(parsed: &ArgMatches) -> ChartKind {
+    let period = match parsed.value_of("cohort") {
+        Some("quarter") => Period::Quarter,
+        _ => Period::Year,
+    };
+    match parsed.value_of("chart") {
+        Some("cohort") => ChartKind::Cohort(period),
+        Some("language") => ChartKind::Language,
+        _ => ChartKind::Author,
+    }
+}
+
+fn get_bucket(parsed: &ArgMatches, config: &ConfigFile) -> Bucket {
+    match parsed.value_of("bucket").or(config.bucket.as_deref()) {
+        Some("week") => Bucket::Period(Period::Week),
+        Some("month") => Bucket::Period(Period::Month),
+        Some("quarter") => Bucket::Period(Period::Quarter),
+        Some("tag") => Bucket::Tag,
+        _ => Bucket::Period(Period::Day),
+    }
+}
+
+/// The depth of the directories plotted in separated panels, the program exits if the output
+/// isn't a chart or the depth isn't a positive number
+fn get_split_depth(parsed: &ArgMatches, format: OutputFormat) -> Option<usize> {
+    if !parsed.is_present("split-dirs") {
+        return None;
+    }
+    if format != OutputFormat::Bitmap && format != OutputFormat::Svg {
+        eprintln!("Error: --split-dirs only works with the png and svg formats");
+        std::process::exit(1);
+    }
+    if parsed.value_of("split-dirs").is_none() {
+        return Some(1);
+    }
+    let depth = value_t_or_exit!(parsed, "split-dirs", usize);
+    if depth == 0 {
+        Error::value_validation_auto("The depth of --split-dirs must be at least 1".to_string())
+            .exit();
+    }
+    Some(depth)
+}
+
+fn get_churn_period(parsed: &ArgMatches) -> Option<Period> {
+    if !parsed.is_present("churn") {
+        return None;
+    }
+    match parsed.value_of("churn-period") {
+        Some("week") => Some(Period::Week),
+        _ => Some(Period::Month),
+    }
+}
+
+fn get_num_tops(parsed: &ArgMatches, config: &ConfigFile) -> usize {
+    match parsed.value_of("top") {
+        Some(top) => top.parse().unwrap(),
+        None => config.top.unwrap_or(5),
+    }
+}
+
+fn get_format(parsed: &ArgMatches, config: &ConfigFile) -> OutputFormat {
+    let format = parsed
+        .value_of("format")
+        .or_else(|| {
+            parsed.value_of("output").map(|path| {
+                Path::new(path).extension().map_or("svg", |ext| ext.to_str().unwrap_or(""))
+            })
+        })
+        .or(config.format.as_deref());
+    match format {
+        Some("svg") => OutputFormat::Svg,
+        Some("csv") => OutputFormat::Csv,
+        Some("json") => OutputFormat::Json,
+        Some("html") => OutputFormat::Html,
+        Some("term") => OutputFormat::Term,
+        Some("gif") => OutputFormat::Gif,
+        _ => OutputFormat::Bitmap,
+    }
 }
 
-fn get_out_path(parsed: &ArgMatches, repo_path: &Path) -> PathBuf {
+fn get_out_path(parsed: &ArgMatches, repo_path: &Path, format: OutputFormat) -> PathBuf {
     parsed
         .value_of("output")
         .map(|x| x.to_string())
         .unwrap_or_else(|| {
             format!(
-                "{}.constat.png",
+                "{}.constat.{}",
                 repo_path
                     .file_name()
                     .unwrap_or_else(|| "unknown-repo".as_ref())
                     .to_string_lossy()
-                    .to_owned()
+                    .to_owned(),
+                match format {
+                    OutputFormat::Bitmap => "png",
+                    OutputFormat::Svg => "svg",
+                    OutputFormat::Csv => "csv",
+                    OutputFormat::Json => "json",
+                    OutputFormat::Html => "html",
+                    OutputFormat::Term => "txt",
+                    OutputFormat::Gif => "gif",
+                }
             )
         })
         .into()
 }
 
-fn get_resolution(parsed: &ArgMatches) -> (u32, u32) {
-    let mut parser = parsed
+fn parse_resolution(resolution: &str) -> Option<(u32, u32)> {
+    let (width, height) = resolution.split_once('x')?;
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
+
+fn get_resolution(parsed: &ArgMatches, config: &ConfigFile) -> (u32, u32) {
+    let resolution = parsed
         .value_of("resolution")
-        .unwrap_or("1024x768")
-        .split(|x| x == 'x')
-        .take(2)
-        .map(|s| s.parse().unwrap());
-    (parser.next().unwrap(), parser.next().unwrap())
+        .or(config.resolution.as_deref())
+        .unwrap_or("1024x768");
+    parse_resolution(resolution).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_is_inferred_from_the_output_extension() {
+        let option_spec = load_yaml!("cli.yml");
+        let cases: &[(&[&str], OutputFormat)] = &[
+            (&["constat", "."], OutputFormat::Bitmap),
+            (&["constat", ".", "-o", "out.png"], OutputFormat::Bitmap),
+            (&["constat", ".", "-o", "out.svg"], OutputFormat::Svg),
+            (&["constat", ".", "-o", "out"], OutputFormat::Svg),
+            (&["constat", ".", "-o", "out.csv"], OutputFormat::Csv),
+            (&["constat", ".", "-o", "out.json"], OutputFormat::Json),
+            (
+                &["constat", ".", "-o", "out.csv", "-f", "json"],
+                OutputFormat::Json,
+            ),
+        ];
+        let config = ConfigFile::default();
+        for (args, format) in cases {
+            let parsed = App::from_yaml(option_spec).get_matches_from(args.iter());
+            assert_eq!(get_format(&parsed, &config), *format, "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn format_on_the_command_line_overrides_the_config() {
+        let option_spec = load_yaml!("cli.yml");
+        let config = ConfigFile {
+            format: Some("csv".to_string()),
+            ..ConfigFile::default()
+        };
+        let parsed = App::from_yaml(option_spec).get_matches_from(["constat", "."]);
+        assert_eq!(get_format(&parsed, &config), OutputFormat::Csv);
+        let parsed = App::from_yaml(option_spec).get_matches_from(["constat", ".", "-o", "a.svg"]);
+        assert_eq!(get_format(&parsed, &config), OutputFormat::Svg);
+    }
+
+    #[test]
+    fn period_labels_sort_in_chronological_order() {
+        // 2019-12-31T23:59:59Z and 2020-04-01T00:00:00Z
+        assert_eq!(Period::Day.label(1_577_836_799), "2019-12-31");
+        assert_eq!(Period::Week.label(1_577_836_799), "2020-W01");
+        assert_eq!(Period::Month.label(1_577_836_799), "2019-12");
+        assert_eq!(Period::Quarter.label(1_577_836_799), "2019Q4");
+        assert_eq!(Period::Year.label(1_577_836_799), "2019");
+        assert_eq!(Period::Quarter.label(1_585_699_200), "2020Q2");
+        assert!(Period::Quarter.label(1_577_836_799) < Period::Quarter.label(1_585_699_200));
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn next_start_of_day_and_week() {
+        assert_eq!(Period::Day.next_start(date(2020, 2, 28)), date(2020, 2, 29));
+        assert_eq!(Period::Day.next_start(date(2020, 12, 31)), date(2021, 1, 1));
+        // 2020-01-01 is a Wednesday, the weeks start on Monday
+        assert_eq!(Period::Week.next_start(date(2020, 1, 1)), date(2020, 1, 6));
+        assert_eq!(Period::Week.next_start(date(2020, 1, 6)), date(2020, 1, 13));
+        assert_eq!(Period::Week.next_start(date(2020, 12, 30)), date(2021, 1, 4));
+    }
+
+    #[test]
+    fn next_start_of_month_rolls_over_the_year() {
+        assert_eq!(Period::Month.next_start(date(2020, 1, 31)), date(2020, 2, 1));
+        assert_eq!(Period::Month.next_start(date(2020, 11, 1)), date(2020, 12, 1));
+        assert_eq!(Period::Month.next_start(date(2020, 12, 15)), date(2021, 1, 1));
+    }
+
+    #[test]
+    fn next_start_of_quarter_rolls_over_the_year() {
+        assert_eq!(Period::Quarter.next_start(date(2020, 1, 1)), date(2020, 4, 1));
+        assert_eq!(Period::Quarter.next_start(date(2020, 3, 31)), date(2020, 4, 1));
+        assert_eq!(Period::Quarter.next_start(date(2020, 4, 1)), date(2020, 7, 1));
+        assert_eq!(Period::Quarter.next_start(date(2020, 11, 2)), date(2021, 1, 1));
+        assert_eq!(Period::Year.next_start(date(2020, 6, 1)), date(2021, 1, 1));
+    }
 }
@@ -1,148 +1,299 @@
-use chrono::{Date, Utc, Duration};
+use chrono::{Date, DateTime, Utc, Duration};
 
+use plotters::coord::Shift;
 use plotters::prelude::PathElement;
+use plotters::style::text_anchor::{HPos, Pos, VPos};
 use plotters::prelude::*;
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::hash::Hash;
 use std::path::Path;
-use super::options::ConstatOptions;
+use super::options::{ChartKind, ConstatOptions, OutputFormat};
+use super::survival::Survival;
 
 pub type AuthorStat = (String, Vec<(Date<Utc>, usize)>);
 
+/// The number of lines owned by each author over time
+pub type AuthorInfo = HashMap<String, BTreeMap<Date<Utc>, usize>>;
+
 pub struct Renderer<D: DrawingBackend> {
     data: Vec<AuthorStat>,
     repo_name: String,
     back: D,
+    percentage: bool,
+    chart: ChartKind,
+    markers: Vec<(String, DateTime<Utc>)>,
+}
+
+/// Expand the stat to the value at each time index it covers, the last value lasts to the end
+fn expand_stat<T: Hash + Eq>(
+    stat: &[(T, usize)],
+    time_table: &HashMap<T, usize>,
+    num_times: usize,
+) -> Vec<(usize, usize)> {
+    let end_points = stat.iter().skip(1).map(|(time, _)| time_table[time]).chain(std::iter::once(num_times - 1));
+    let start_points = stat.iter().map(|(time, _)| time_table[time]);
+    start_points
+        .zip(end_points)
+        .zip(stat.iter())
+        .flat_map(|((start, end), (_, value))| (start..end).map(move |idx| (idx, *value)))
+        .collect()
+}
+
+pub fn chart_title(chart: ChartKind, percentage: bool) -> &'static str {
+    match (chart, percentage) {
+        (ChartKind::Author, false) => "Contributor Stat",
+        (ChartKind::Author, true) => "Contributor Share",
+        (ChartKind::Cohort(_), false) => "Code Age",
+        (ChartKind::Cohort(_), true) => "Code Age Share",
+        (ChartKind::Language, false) => "Language Stat",
+        (ChartKind::Language, true) => "Language Share",
+    }
+}
+
+pub fn repo_name(path: &Path) -> String {
+    path.file_name()
+        .map_or("N/A".to_string(), |what| what.to_string_lossy().into_owned())
 }
 
 impl<D: DrawingBackend> Renderer<D> {
     pub fn new<P: AsRef<Path>>(path: P, data: Vec<AuthorStat>, back: D) -> Self {
         Self {
-            repo_name: path.as_ref().file_name().map_or("N/A".to_string(), |what| {
-                what.to_string_lossy().into_owned()
-            }),
+            repo_name: repo_name(path.as_ref()),
             data,
             back,
+            percentage: false,
+            chart: ChartKind::Author,
+            markers: vec![],
         }
     }
 
+    /// Normalize the stack, so that it shows the share of each author at every time point
+    pub fn with_percentage(mut self, percentage: bool) -> Self {
+        self.percentage = percentage;
+        self
+    }
+
+    /// Set what the stack is grouped by, which is only used to caption the chart
+    pub fn with_chart(mut self, chart: ChartKind) -> Self {
+        self.chart = chart;
+        self
+    }
+
+    /// Draw a labeled vertical line at each of the times, for example the tags
+    pub fn with_markers(mut self, markers: Vec<(String, DateTime<Utc>)>) -> Self {
+        self.markers = markers;
+        self
+    }
+
     pub fn draw(self) {
-        let min_time = self.data[0].1.first().unwrap().0;
-        let max_time = self
-            .data
-            .iter()
-            .map(|(_, stats)| stats.last().unwrap().0)
-            .max()
-            .unwrap();
-        let max_loc = self
-            .data
-            .iter()
-            .map(|(_, stats)| stats.iter().map(|x| x.1).max().unwrap())
-            .sum::<usize>();
+        let title = chart_title(self.chart, self.percentage);
 
         let root = self.back.into_drawing_area();
 
         root.fill(&WHITE).unwrap();
 
-        let mut chart = ChartBuilder::on(&root)
-            .set_label_area_size(LabelAreaPosition::Left, (10).percent_width())
-            .set_label_area_size(LabelAreaPosition::Bottom, (10).percent_height())
-            .margin(10)
-            .caption(
-                format!("Contributor Stat for {}", self.repo_name),
-                ("Arial", (5).percent_height()),
-            )
-            .build_ranged(min_time..max_time, 0..(max_loc))
-            .unwrap();
-
-        chart
-            .configure_mesh()
-            .disable_x_mesh()
-            .disable_y_mesh()
-            .draw()
-            .unwrap();
+        draw_stack(
+            &root,
+            &format!("{} for {}", title, self.repo_name),
+            self.data,
+            self.percentage,
+            &self.markers,
+            None,
+        );
+    }
+}
 
-        let (time_table, time_values) = {
-            let mut time_values: Vec<_> = self
-                .data
-                .iter()
-                .map(|(_, stat)| {
-                    stat.iter().map(|(time, _)|{
-                        let time = time.clone() - Duration::days(1);
-                        (0..2).map(move |days| {
-                            time.clone() + Duration::days(days)
-                        })
-                    }).flatten()
-                })
-                .flatten()
-                .collect();
-            time_values.sort();
-            let mut j = 1;
-            for i in 1..time_values.len() {
-                if time_values[j - 1] != time_values[i] {
-                    time_values[j] = time_values[i];
-                    j += 1;
-                }
+/// Draw the stacked chart on the area. The color of each series is picked by its index in the
+/// colors if given, otherwise by its position in the stack.
+fn draw_stack<D: DrawingBackend>(
+    area: &DrawingArea<D, Shift>,
+    caption: &str,
+    data: Vec<AuthorStat>,
+    percentage: bool,
+    markers: &[(String, DateTime<Utc>)],
+    colors: Option<&HashMap<String, usize>>,
+) {
+    let min_time = data[0].1.first().unwrap().0;
+    let max_time = data
+        .iter()
+        .map(|(_, stats)| stats.last().unwrap().0)
+        .max()
+        .unwrap();
+    // A chart of a single date still needs a range to draw
+    let max_time = if max_time == min_time {
+        max_time + Duration::days(1)
+    } else {
+        max_time
+    };
+    let max_loc = if percentage {
+        10000
+    } else {
+        data.iter()
+            .map(|(_, stats)| stats.iter().map(|x| x.1).max().unwrap())
+            .sum::<usize>()
+    };
+    let mut chart = ChartBuilder::on(area)
+        .set_label_area_size(LabelAreaPosition::Left, (10).percent_width())
+        .set_label_area_size(LabelAreaPosition::Bottom, (10).percent_height())
+        .margin(10)
+        .caption(caption, ("Arial", (5).percent_height()))
+        .build_ranged(min_time..max_time, 0..(max_loc))
+        .unwrap();
+
+    let percentage_formatter = |y: &usize| format!("{}%", y / 100);
+    let count_formatter = |y: &usize| y.to_string();
+
+    // Keep the dates from overlapping on a narrow area, like a panel of a grid
+    let x_labels = (area.dim_in_pixel().0 as usize / 100).clamp(2, 10);
+
+    chart
+        .configure_mesh()
+        .disable_x_mesh()
+        .disable_y_mesh()
+        .x_labels(x_labels)
+        .y_label_formatter(if percentage {
+            &percentage_formatter
+        } else {
+            &count_formatter
+        })
+        .draw()
+        .unwrap();
+
+    let (time_table, time_values) = {
+        let mut time_values: Vec<_> = data
+            .iter()
+            .map(|(_, stat)| {
+                stat.iter().map(|(time, _)|{
+                    let time = time.clone() - Duration::days(1);
+                    (0..2).map(move |days| {
+                        time.clone() + Duration::days(days)
+                    })
+                }).flatten()
+            })
+            .flatten()
+            .collect();
+        time_values.sort();
+        let mut j = 1;
+        for i in 1..time_values.len() {
+            if time_values[j - 1] != time_values[i] {
+                time_values[j] = time_values[i];
+                j += 1;
             }
-            time_values.truncate(j);
-            (
-                time_values
-                    .iter()
-                    .zip(0..)
-                    .map(|(time, idx)| (time.clone(), idx))
-                    .collect::<HashMap<_, _>>(),
-                time_values,
-            )
-        };
+        }
+        time_values.truncate(j);
+        (
+            time_values
+                .iter()
+                .zip(0..)
+                .map(|(time, idx)| (time.clone(), idx))
+                .collect::<HashMap<_, _>>(),
+            time_values,
+        )
+    };
 
-        let mut accumulate = vec![0; time_values.len()];
+    let series: Vec<_> = data
+        .into_iter()
+        .map(|(name, stat)| (name, expand_stat(&stat, &time_table, time_values.len())))
+        .collect();
 
-        for (i, (name, stat)) in (0..).zip(self.data.into_iter()) {
-            let mut points = vec![];
-            let mut back_points = vec![];
+    let mut totals = vec![0; time_values.len()];
+    for (_, values) in series.iter() {
+        for &(idx, value) in values {
+            totals[idx] += value;
+        }
+    }
 
-            let end_points = stat.iter().skip(1).map(|(time, _)| time_table[time]).chain(std::iter::once(time_values.len() - 1));
-            let start_points = stat.iter().map(|(time, _)| time_table[time]);
-            let interval_iter = start_points.zip(end_points).enumerate().map(|(idx, (start, end))| (idx, start, end));
+    let scale = |idx: usize, value: usize| {
+        if percentage {
+            (value * 10000).checked_div(totals[idx]).unwrap_or(0)
+        } else {
+            value
+        }
+    };
 
-            for (stat_idx, start, end) in interval_iter {
-                for idx in start..end {
-                    back_points.push((time_values[idx], accumulate[idx]));
-                    accumulate[idx] += stat[stat_idx].1;
-                    points.push((time_values[idx], accumulate[idx]));
-                }
-            }
+    let mut accumulate = vec![0; time_values.len()];
 
-            let c = Palette99::pick(i);
-            chart
-                .draw_series(std::iter::once(Polygon::new(
-                    points
-                        .clone()
-                        .into_iter()
-                        .chain(back_points.into_iter().rev())
-                        .collect::<Vec<_>>(),
-                    &c.mix(0.4),
-                )))
-                .unwrap();
-            chart
-                .draw_series(std::iter::once(PathElement::new(points.clone(), &c)))
-                .unwrap()
-                .label(name)
-                .legend(move |(x, y)| {
-                    Rectangle::new([(x, y - 5), (x + 20, y + 5)], c.mix(0.4).filled())
-                });
+    for (i, (name, values)) in (0..).zip(series) {
+        let mut points = vec![];
+        let mut back_points = vec![];
+
+        for (idx, value) in values {
+            back_points.push((time_values[idx], scale(idx, accumulate[idx])));
+            accumulate[idx] += value;
+            points.push((time_values[idx], scale(idx, accumulate[idx])));
         }
 
+        let c = Palette99::pick(colors.and_then(|colors| colors.get(&name)).copied().unwrap_or(i));
         chart
-            .configure_series_labels()
-            .position(SeriesLabelPosition::UpperLeft)
-            .border_style(&BLACK)
-            .draw()
+            .draw_series(std::iter::once(Polygon::new(
+                points
+                    .clone()
+                    .into_iter()
+                    .chain(back_points.into_iter().rev())
+                    .collect::<Vec<_>>(),
+                &c.mix(0.4),
+            )))
             .unwrap();
+        chart
+            .draw_series(std::iter::once(PathElement::new(points.clone(), &c)))
+            .unwrap()
+            .label(name)
+            .legend(move |(x, y)| {
+                Rectangle::new([(x, y - 5), (x + 20, y + 5)], c.mix(0.4).filled())
+            });
     }
+
+    let span = (max_time.naive_utc() - min_time.naive_utc()).num_days();
+    for (name, time) in markers {
+        let days = (time.date_naive() - min_time.naive_utc()).num_days();
+        if days < 0 || days > span {
+            continue;
+        }
+        let date = min_time + Duration::days(days);
+        chart
+            .draw_series(std::iter::once(PathElement::new(
+                vec![(date, 0), (date, max_loc)],
+                &BLACK.mix(0.5),
+            )))
+            .unwrap();
+        // The labels in the right half are drawn to the left of the markers, so that a tag near
+        // the end doesn't run off the chart
+        let (anchor, offset) = if days * 2 > span {
+            (HPos::Right, -4)
+        } else {
+            (HPos::Left, 4)
+        };
+        let style = TextStyle::from(("Arial", 12).into_font()).pos(Pos::new(anchor, VPos::Top));
+        chart
+            .draw_series(std::iter::once(
+                EmptyElement::at((date, max_loc)) + Text::new(name.clone(), (offset, 0), style),
+            ))
+            .unwrap();
+    }
+
+    chart
+        .configure_series_labels()
+        .position(SeriesLabelPosition::UpperLeft)
+        .border_style(&BLACK)
+        .draw()
+        .unwrap();
+}
+
+/// Every cohort is plotted, from the oldest one at the bottom of the stack
+fn cohort_series(author_info: &mut AuthorInfo) -> Vec<AuthorStat> {
+    let mut buf: Vec<_> = author_info
+        .drain()
+        .map(|(label, stat)| (label, stat.into_iter().collect()))
+        .collect();
+    buf.sort_by(|a: &AuthorStat, b| a.0.cmp(&b.0));
+    buf
 }
 
-pub fn render_plot(author_info: &mut HashMap<String, BTreeMap<Date<Utc>, usize>>, options: &ConstatOptions) {
-    let author_info = {
+/// The series of the stack, either all the cohorts or the top authors and the others
+pub fn stack_series(author_info: &mut AuthorInfo, options: &ConstatOptions) -> Vec<AuthorStat> {
+    if let ChartKind::Cohort(_) = options.chart {
+        cohort_series(author_info)
+    } else {
         let exclude_older = options.exclude_older;
         let mut max_loc: Vec<_> = author_info
             .iter()
@@ -198,18 +349,62 @@ pub fn render_plot(author_info: &mut HashMap<String, BTreeMap<Date<Utc>, usize>>
         buf.sort_by_key(|(_name, stats)| stats.first().unwrap().0);
 
         buf
-    };
+    }
+}
 
-    if options
-        .out_path
-        .extension()
-        .map_or(true, |ext| ext == "svg")
-    {
+/// The lines owned by each series at the end, the largest first
+pub fn final_ownership(author_info: &AuthorInfo, options: &ConstatOptions) -> Vec<(String, usize)> {
+    let mut owners: Vec<_> = author_info
+        .iter()
+        .filter(|(name, _)| !(options.exclude_older && name.as_str() == "Older Code"))
+        .filter_map(|(name, stat)| Some((name.to_string(), *stat.values().next_back()?)))
+        .filter(|(_, lines)| *lines > 0)
+        .collect();
+    owners.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    owners
+}
+
+/// What the series of the chart are, which is the header of the ownership tables
+pub fn name_column(options: &ConstatOptions) -> &'static str {
+    match options.chart {
+        ChartKind::Author if options.teams.is_some() => "Team",
+        ChartKind::Author => "Author",
+        ChartKind::Cohort(_) => "Cohort",
+        ChartKind::Language => "Language",
+    }
+}
+
+/// Check if any author shown on the chart owns a line at some date
+fn has_lines(author_info: &AuthorInfo, exclude_older: bool) -> bool {
+    author_info
+        .iter()
+        .filter(|(name, _)| !(exclude_older && name.as_str() == "Older Code"))
+        .any(|(_, stat)| stat.values().any(|&count| count > 0))
+}
+
+pub fn render_plot(
+    author_info: &mut AuthorInfo,
+    markers: Vec<(String, DateTime<Utc>)>,
+    options: &ConstatOptions,
+) {
+    // The history is empty when it ends before the first commit or the path filter matches no
+    // files, there's nothing to draw
+    if !has_lines(author_info, options.exclude_older) {
+        eprintln!("Error: No lines found, {} isn't written", options.out_path.display());
+        std::process::exit(1);
+    }
+
+    let author_info = stack_series(author_info, options);
+
+    if options.format == OutputFormat::Svg {
         let renderer = Renderer::new(
             &options.repo_path,
             author_info,
             SVGBackend::new(&options.out_path, options.resolution),
-        );
+        )
+        .with_percentage(options.percentage)
+        .with_chart(options.chart)
+        .with_markers(markers);
 
         renderer.draw();
     } else {
@@ -217,9 +412,346 @@ pub fn render_plot(author_info: &mut HashMap<String, BTreeMap<Date<Utc>, usize>>
             &options.repo_path,
             author_info,
             BitMapBackend::new(&options.out_path, options.resolution),
-        );
+        )
+        .with_percentage(options.percentage)
+        .with_chart(options.chart)
+        .with_markers(markers);
 
         renderer.draw();
     }
 }
 
+/// The most panels drawn in a grid, the directories owning fewer lines are left out
+const MAX_PANELS: usize = 16;
+
+fn draw_grid<D: DrawingBackend>(
+    back: D,
+    mut panels: Vec<(String, AuthorInfo)>,
+    markers: &[(String, DateTime<Utc>)],
+    options: &ConstatOptions,
+) {
+    // The directories owning the most lines at the end come first
+    let final_lines = |info: &AuthorInfo| -> usize {
+        info.values()
+            .filter_map(|stat| stat.values().next_back())
+            .sum()
+    };
+    // The authors who never own a line in a directory are left out of its panel
+    for (_, info) in panels.iter_mut() {
+        info.retain(|_, stat| stat.values().any(|&count| count > 0));
+    }
+    panels.retain(|(_, info)| !info.is_empty());
+    panels.sort_by(|a, b| final_lines(&b.1).cmp(&final_lines(&a.1)).then(a.0.cmp(&b.0)));
+    panels.truncate(MAX_PANELS);
+
+    // A directory that only shows up at the last sample has no span to draw, so it's left out
+    let panels: Vec<_> = panels
+        .into_iter()
+        .map(|(dir, mut info)| (dir, stack_series(&mut info, options)))
+        .filter(|(_, series)| {
+            let dates: BTreeSet<_> = series
+                .iter()
+                .flat_map(|(_, stat)| stat.iter().map(|(date, _)| date))
+                .collect();
+            dates.len() > 1
+        })
+        .collect();
+
+    // The same author has the same color in every panel
+    let mut colors = HashMap::new();
+    for (_, series) in panels.iter() {
+        for (name, _) in series {
+            let next = colors.len();
+            colors.entry(name.clone()).or_insert(next);
+        }
+    }
+
+    let root = back.into_drawing_area();
+
+    root.fill(&WHITE).unwrap();
+
+    let title = format!(
+        "{} for {}",
+        chart_title(options.chart, options.percentage),
+        repo_name(&options.repo_path)
+    );
+    let root = root.titled(&title, ("Arial", 24)).unwrap();
+
+    let cols = (1..).find(|cols| cols * cols >= panels.len()).unwrap();
+    let rows = ((panels.len() + cols - 1) / cols).max(1);
+
+    for ((dir, series), area) in panels.into_iter().zip(root.split_evenly((rows, cols))) {
+        draw_stack(&area, &dir, series, options.percentage, markers, Some(&colors));
+    }
+}
+
+/// Plot the ownership of each directory in its own panel of a grid
+pub fn render_grid(
+    panels: Vec<(String, AuthorInfo)>,
+    markers: Vec<(String, DateTime<Utc>)>,
+    options: &ConstatOptions,
+) {
+    if !panels.iter().any(|(_, info)| has_lines(info, options.exclude_older)) {
+        eprintln!("Error: No lines found, {} isn't written", options.out_path.display());
+        std::process::exit(1);
+    }
+    if options.format == OutputFormat::Svg {
+        draw_grid(SVGBackend::new(&options.out_path, options.resolution), panels, &markers, options);
+    } else {
+        draw_grid(BitMapBackend::new(&options.out_path, options.resolution), panels, &markers, options);
+    }
+}
+
+/// The times the last frame of an animation is repeated, so that it stays a while before the
+/// animation loops
+const FINAL_FRAME_REPEATS: usize = 4;
+
+/// Draw a bar chart of the top authors at each date as a frame of the animation, every frame has
+/// the same scale so that the bars grow and shrink over time
+fn draw_animation<D: DrawingBackend>(
+    back: D,
+    author_info: &AuthorInfo,
+    markers: &[(String, DateTime<Utc>)],
+    options: &ConstatOptions,
+) {
+    // The lines owned by an author are carried over from the last date before the frame
+    let value_at = |stat: &BTreeMap<_, usize>, date| {
+        stat.range(..=date).next_back().map_or(0, |(_, &count)| count)
+    };
+    let authors: Vec<_> = author_info
+        .iter()
+        .filter(|(name, _)| !(options.exclude_older && name.as_str() == "Older Code"))
+        .collect();
+    let dates: BTreeSet<_> = authors.iter().flat_map(|(_, stat)| stat.keys()).collect();
+
+    let frames: Vec<_> = dates
+        .into_iter()
+        .map(|date| {
+            let mut ranked: Vec<_> = authors
+                .iter()
+                .map(|(name, stat)| (name.as_str(), value_at(stat, *date)))
+                .filter(|(_, count)| *count > 0)
+                .collect();
+            ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+            let total: usize = ranked.iter().map(|(_, count)| count).sum();
+            if options.percentage {
+                for (_, count) in ranked.iter_mut() {
+                    *count = *count * 10000 / total;
+                }
+            }
+            ranked.truncate(options.top);
+            (date, ranked)
+        })
+        .collect();
+
+    // An author keeps the color from the first frame the author shows up in
+    let mut colors = HashMap::new();
+    for (_, ranked) in frames.iter() {
+        for (name, _) in ranked {
+            let next = colors.len();
+            colors.entry(*name).or_insert(next);
+        }
+    }
+
+    let max_count = if options.percentage {
+        10000
+    } else {
+        frames
+            .iter()
+            .filter_map(|(_, ranked)| ranked.first())
+            .map(|(_, count)| *count)
+            .max()
+            .unwrap_or(1)
+    };
+    let bars = options.top as f64;
+
+    let title = format!(
+        "{} for {}",
+        chart_title(options.chart, options.percentage),
+        repo_name(&options.repo_path)
+    );
+    let percentage_formatter = |x: &usize| format!("{}%", x / 100);
+    let count_formatter = |x: &usize| x.to_string();
+
+    let root = back.into_drawing_area();
+
+    for (idx, (date, ranked)) in frames.iter().enumerate() {
+        root.fill(&WHITE).unwrap();
+
+        // The date of the frame and the last tag before it
+        let tag = markers
+            .iter()
+            .filter(|(_, time)| time.date_naive() <= date.naive_utc())
+            .max_by_key(|(_, time)| *time);
+        let caption = match tag {
+            Some((name, _)) => format!("{} - {} ({})", title, date.format("%Y-%m-%d"), name),
+            None => format!("{} - {}", title, date.format("%Y-%m-%d")),
+        };
+
+        let mut chart = ChartBuilder::on(&root)
+            .set_label_area_size(LabelAreaPosition::Bottom, (10).percent_height())
+            .margin(20)
+            .caption(caption, ("Arial", (5).percent_height()))
+            .build_ranged(0..max_count, 0.0..bars)
+            .unwrap();
+
+        chart
+            .configure_mesh()
+            .disable_y_mesh()
+            .disable_y_axis()
+            .line_style_2(&WHITE)
+            .x_label_formatter(if options.percentage {
+                &percentage_formatter
+            } else {
+                &count_formatter
+            })
+            .draw()
+            .unwrap();
+
+        // The top author is at the top, and the labels are at the start of the bars so that they
+        // don't run off the chart
+        for (rank, (name, count)) in ranked.iter().enumerate() {
+            let top = bars - rank as f64 - 0.1;
+            let bottom = bars - rank as f64 - 0.9;
+            let c = Palette99::pick(colors[name]);
+            let label = if options.percentage {
+                format!("{} {:.1}%", name, *count as f64 / 100.0)
+            } else {
+                format!("{} {}", name, count)
+            };
+            chart
+                .draw_series(std::iter::once(Rectangle::new(
+                    [(0, top), (*count, bottom)],
+                    c.mix(0.6).filled(),
+                )))
+                .unwrap();
+            chart
+                .draw_series(std::iter::once(
+                    EmptyElement::at((0, (top + bottom) / 2.0))
+                        + Text::new(label, (8, -8), ("Arial", 16)),
+                ))
+                .unwrap();
+        }
+
+        let repeats = if idx + 1 == frames.len() {
+            1 + FINAL_FRAME_REPEATS
+        } else {
+            1
+        };
+        for _ in 0..repeats {
+            root.present().unwrap();
+        }
+    }
+}
+
+/// Animate the ownership of the top authors at each date as a gif
+pub fn render_animation(
+    author_info: &AuthorInfo,
+    markers: &[(String, DateTime<Utc>)],
+    options: &ConstatOptions,
+) {
+    // A gif without any frame isn't a valid animation
+    if !has_lines(author_info, options.exclude_older) {
+        eprintln!("Error: No lines found, {} isn't written", options.out_path.display());
+        std::process::exit(1);
+    }
+    let back = match BitMapBackend::gif(&options.out_path, options.resolution, options.frame_delay) {
+        Ok(back) => back,
+        Err(err) => {
+            eprintln!("Error: Cannot write {}: {}", options.out_path.display(), err);
+            std::process::exit(1);
+        }
+    };
+    draw_animation(back, author_info, markers, options);
+}
+
+fn draw_survival<D: DrawingBackend>(back: D, repo_name: &str, curves: &[(&str, &Survival)]) {
+    let max_days = curves
+        .iter()
+        .map(|(_, survival)| survival.max_age as f64 / 86400.0)
+        .fold(1.0, f64::max);
+
+    let root = back.into_drawing_area();
+
+    root.fill(&WHITE).unwrap();
+
+    let mut chart = ChartBuilder::on(&root)
+        .set_label_area_size(LabelAreaPosition::Left, (10).percent_width())
+        .set_label_area_size(LabelAreaPosition::Bottom, (10).percent_height())
+        .margin(10)
+        .caption(
+            format!("Line Survival for {}", repo_name),
+            ("Arial", (5).percent_height()),
+        )
+        .build_ranged(0.0..max_days, 0.0..1.0)
+        .unwrap();
+
+    chart
+        .configure_mesh()
+        .disable_x_mesh()
+        .disable_y_mesh()
+        .x_desc("Age (days)")
+        .y_label_formatter(&|y: &f64| format!("{:.0}%", y * 100.0))
+        .draw()
+        .unwrap();
+
+    for (i, (name, survival)) in curves.iter().enumerate() {
+        // The survival stays the same until the next age when some lines are removed
+        let mut points = vec![];
+        let mut last = 1.0;
+        for &(age, value) in survival.curve.iter() {
+            let days = age as f64 / 86400.0;
+            points.push((days, last));
+            points.push((days, value));
+            last = value;
+        }
+        points.push((survival.max_age as f64 / 86400.0, last));
+
+        let c = Palette99::pick(i);
+        chart
+            .draw_series(std::iter::once(PathElement::new(points, &c)))
+            .unwrap()
+            .label(name.to_string())
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &c));
+    }
+
+    chart
+        .configure_series_labels()
+        .position(SeriesLabelPosition::UpperRight)
+        .border_style(&BLACK)
+        .draw()
+        .unwrap();
+}
+
+/// Plot the survival curves of the lines to the output file
+pub fn render_survival(curves: &[(&str, &Survival)], options: &ConstatOptions) {
+    let repo_name = repo_name(&options.repo_path);
+    if options.format == OutputFormat::Svg {
+        draw_survival(SVGBackend::new(&options.out_path, options.resolution), &repo_name, curves);
+    } else {
+        draw_survival(BitMapBackend::new(&options.out_path, options.resolution), &repo_name, curves);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn no_lines_when_the_filter_matches_no_files() {
+        assert!(!has_lines(&HashMap::new(), false));
+
+        // The authors of the files filtered out own no lines at any date
+        let date = Utc.timestamp_opt(1_577_836_800, 0).unwrap().date();
+        let mut author_info: AuthorInfo = HashMap::new();
+        let stat = vec![(date, 0)].into_iter().collect();
+        author_info.insert("Jane Doe".to_string(), stat);
+        assert!(!has_lines(&author_info, false));
+
+        let stat = vec![(date, 3)].into_iter().collect();
+        author_info.insert("Older Code".to_string(), stat);
+        assert!(has_lines(&author_info, false));
+        assert!(!has_lines(&author_info, true));
+    }
+}
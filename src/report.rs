@@ -0,0 +1,225 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+/// The lines owned by each author in a single file
+pub type FileOwnership = (PathBuf, Vec<(String, usize)>);
+
+/// Aggregate the file ownership to every directory containing the file. If the depth is given,
+/// only the entries that are not deeper than the depth are kept. The boolean indicates if the
+/// entry is a directory.
+pub fn aggregate_ownership(
+    files: &[FileOwnership],
+    depth: Option<usize>,
+) -> BTreeMap<PathBuf, (bool, HashMap<String, usize>)> {
+    let mut ret: BTreeMap<PathBuf, (bool, HashMap<String, usize>)> = BTreeMap::new();
+
+    for (path, owners) in files {
+        let levels = path.components().count();
+        let mut prefix = PathBuf::new();
+        let mut entries = vec![(PathBuf::from("."), true)];
+        for (level, component) in path.components().enumerate() {
+            if depth.map_or(false, |depth| level >= depth) {
+                break;
+            }
+            prefix.push(component);
+            entries.push((prefix.clone(), level + 1 < levels));
+        }
+        for (entry, is_dir) in entries {
+            let cell = ret.entry(entry).or_insert_with(|| (is_dir, HashMap::new()));
+            for (author, count) in owners {
+                *cell.1.entry(author.clone()).or_default() += count;
+            }
+        }
+    }
+
+    ret
+}
+
+/// Sort the owners by the number of lines they own, the largest first
+pub fn rank_owners(owners: &HashMap<String, usize>) -> Vec<(&str, usize)> {
+    let mut ranked: Vec<_> = owners
+        .iter()
+        .filter(|(_, &count)| count > 0)
+        .map(|(name, &count)| (name.as_str(), count))
+        .collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    ranked
+}
+
+fn display_path(path: &Path, is_dir: bool) -> String {
+    if is_dir && path != Path::new(".") {
+        format!("{}/", path.display())
+    } else {
+        path.display().to_string()
+    }
+}
+
+fn format_percentages(ranked: &[(&str, usize)], total: usize, limit: usize) -> String {
+    let mut parts: Vec<_> = ranked
+        .iter()
+        .take(limit)
+        .map(|(name, count)| format!("{} {:.1}%", name, *count as f64 * 100.0 / total as f64))
+        .collect();
+    if ranked.len() > limit {
+        let others: usize = ranked[limit..].iter().map(|(_, count)| count).sum();
+        parts.push(format!("Others {:.1}%", others as f64 * 100.0 / total as f64));
+    }
+    parts.join(", ")
+}
+
+/// Print the table of the lines, top owner and owner percentages of each file and directory
+pub fn print_ownership_report(files: &[FileOwnership], depth: Option<usize>, top: usize) {
+    let mut rows = vec![(
+        "Path".to_string(),
+        "Lines".to_string(),
+        "Top Owner".to_string(),
+        "Ownership".to_string(),
+    )];
+
+    for (path, (is_dir, owners)) in aggregate_ownership(files, depth) {
+        let ranked = rank_owners(&owners);
+        let total: usize = ranked.iter().map(|(_, count)| count).sum();
+        if total == 0 {
+            continue;
+        }
+        rows.push((
+            display_path(&path, is_dir),
+            total.to_string(),
+            ranked[0].0.to_string(),
+            format_percentages(&ranked, total, top),
+        ));
+    }
+
+    print_table(rows);
+}
+
+/// Print the rows with the first three columns aligned
+pub fn print_table(rows: Vec<(String, String, String, String)>) {
+    let rows: Vec<_> = rows.into_iter().map(|(a, b, c, d)| vec![a, b, c, d]).collect();
+    print_columns(&rows, &[false, true, false, false]);
+}
+
+/// Print the rows with the columns aligned, either to the left or the right. The last column
+/// isn't padded if it's aligned to the left.
+pub fn print_columns(rows: &[Vec<String>], right_aligned: &[bool]) {
+    let widths: Vec<_> = (0..right_aligned.len())
+        .map(|idx| rows.iter().map(|row| row[idx].chars().count()).max().unwrap_or(0))
+        .collect();
+
+    for row in rows {
+        let cells: Vec<_> = row
+            .iter()
+            .zip(widths.iter().zip(right_aligned))
+            .enumerate()
+            .map(|(idx, (cell, (&width, &right)))| {
+                if right {
+                    format!("{:>w$}", cell, w = width)
+                } else if idx + 1 == row.len() {
+                    cell.clone()
+                } else {
+                    format!("{:<w$}", cell, w = width)
+                }
+            })
+            .collect();
+        println!("{}", cells.join("  "));
+    }
+}
+
+/// The minimum number of authors who together own more than half of the lines
+pub fn bus_factor(ranked: &[(&str, usize)]) -> usize {
+    let total: usize = ranked.iter().map(|(_, count)| count).sum();
+    let mut owned = 0;
+    for (idx, (_, count)) in ranked.iter().enumerate() {
+        owned += count;
+        if owned * 2 > total {
+            return idx + 1;
+        }
+    }
+    ranked.len()
+}
+
+/// Print the bus factor of the repository and each directory, and the files mostly owned by
+/// a single author
+pub fn print_bus_factor_report(files: &[FileOwnership], depth: Option<usize>, threshold: f64) {
+    let mut rows = vec![(
+        "Directory".to_string(),
+        "Lines".to_string(),
+        "Bus Factor".to_string(),
+        "Key Authors".to_string(),
+    )];
+
+    for (path, (is_dir, owners)) in aggregate_ownership(files, depth) {
+        let ranked = rank_owners(&owners);
+        if !is_dir || ranked.is_empty() {
+            continue;
+        }
+        let factor = bus_factor(&ranked);
+        rows.push((
+            display_path(&path, is_dir),
+            ranked.iter().map(|(_, count)| count).sum::<usize>().to_string(),
+            factor.to_string(),
+            ranked[..factor]
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>()
+                .join(", "),
+        ));
+    }
+
+    print_table(rows);
+
+    let mut rows = vec![(
+        "File".to_string(),
+        "Lines".to_string(),
+        "Owner".to_string(),
+        "Share".to_string(),
+    )];
+
+    for (path, owners) in files {
+        let owners: HashMap<_, _> = owners.iter().cloned().collect();
+        let ranked = rank_owners(&owners);
+        let total: usize = ranked.iter().map(|(_, count)| count).sum();
+        if let Some((name, count)) = ranked.first() {
+            let share = *count as f64 * 100.0 / total as f64;
+            if share > threshold {
+                rows.push((
+                    path.display().to_string(),
+                    total.to_string(),
+                    name.to_string(),
+                    format!("{:.1}%", share),
+                ));
+            }
+        }
+    }
+
+    println!();
+    println!(
+        "{} files are owned by a single author for more than {}%",
+        rows.len() - 1,
+        threshold
+    );
+
+    if rows.len() > 1 {
+        println!();
+        print_table(rows);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bus_factor_needs_more_than_half() {
+        assert_eq!(bus_factor(&[("a", 6), ("b", 4)]), 1);
+        // Owning exactly half of the lines isn't enough
+        assert_eq!(bus_factor(&[("a", 5), ("b", 5)]), 2);
+        assert_eq!(bus_factor(&[("a", 3), ("b", 3), ("c", 3), ("d", 1)]), 2);
+        assert_eq!(bus_factor(&[("a", 1), ("b", 1), ("c", 1)]), 2);
+    }
+
+    #[test]
+    fn bus_factor_of_nothing() {
+        assert_eq!(bus_factor(&[]), 0);
+    }
+}
@@ -0,0 +1,184 @@
+use super::report::print_table;
+use std::collections::HashMap;
+
+/// The lines written by an author at the birth time and removed at the death time, which is
+/// stored in the cache
+pub type LineDeath = (String, i64, i64, usize);
+
+/// The lines written by an author at the birth time that are still alive
+pub type LineBirth = (String, i64, usize);
+
+/// The Kaplan-Meier estimate of how long the lines survive, the ages are in seconds
+pub struct Survival {
+    /// The share of the lines that survive right after each age when some lines are removed
+    pub curve: Vec<(i64, f64)>,
+    pub lines: usize,
+    pub removed: usize,
+    /// The age when half of the lines are removed, None if it's not reached yet
+    pub half_life: Option<i64>,
+    /// The age of the oldest observed line
+    pub max_age: i64,
+}
+
+/// Estimate the survival from the observations of age, number of lines and whether the lines
+/// are removed at that age. The lines still alive are censored at their current age.
+pub fn kaplan_meier(mut observations: Vec<(i64, usize, bool)>) -> Survival {
+    observations.sort_by_key(|(age, _, _)| *age);
+
+    let lines: usize = observations.iter().map(|(_, count, _)| count).sum();
+    let removed: usize = observations
+        .iter()
+        .filter(|(_, _, removed)| *removed)
+        .map(|(_, count, _)| count)
+        .sum();
+
+    let mut at_risk = lines;
+    let mut survival = 1.0;
+    let mut curve = vec![(0, 1.0)];
+    let mut half_life = None;
+
+    let mut idx = 0;
+    while idx < observations.len() {
+        let age = observations[idx].0;
+        let (mut deaths, mut censored) = (0, 0);
+        while idx < observations.len() && observations[idx].0 == age {
+            let (_, count, removed) = observations[idx];
+            if removed {
+                deaths += count;
+            } else {
+                censored += count;
+            }
+            idx += 1;
+        }
+        if deaths > 0 {
+            survival *= 1.0 - deaths as f64 / at_risk as f64;
+            curve.push((age, survival));
+            if half_life.is_none() && survival <= 0.5 {
+                half_life = Some(age);
+            }
+        }
+        at_risk -= deaths + censored;
+    }
+
+    Survival {
+        curve,
+        lines,
+        removed,
+        half_life,
+        max_age: observations.last().map_or(0, |(age, _, _)| *age),
+    }
+}
+
+/// Estimate the survival of the lines of the whole repository and each author, the authors are
+/// sorted by the number of lines they have written, the largest first
+pub fn analyze_survival(
+    deaths: &[LineDeath],
+    alive: &[LineBirth],
+    now: i64,
+) -> (Survival, Vec<(String, Survival)>) {
+    let mut observations: HashMap<&str, Vec<_>> = HashMap::new();
+
+    for (author, birth, death, count) in deaths {
+        observations
+            .entry(author)
+            .or_default()
+            .push((death - birth, *count, true));
+    }
+    for (author, birth, count) in alive {
+        observations
+            .entry(author)
+            .or_default()
+            .push((now - birth, *count, false));
+    }
+
+    let all = kaplan_meier(observations.values().flatten().cloned().collect());
+
+    let mut authors: Vec<_> = observations
+        .into_iter()
+        .map(|(author, observations)| (author.to_string(), kaplan_meier(observations)))
+        .collect();
+    authors.sort_by(|a, b| b.1.lines.cmp(&a.1.lines).then(a.0.cmp(&b.0)));
+
+    (all, authors)
+}
+
+fn format_age(seconds: i64) -> String {
+    format!("{} days", seconds / 86400)
+}
+
+fn format_half_life(survival: &Survival) -> String {
+    match survival.half_life {
+        Some(age) => format_age(age),
+        None => format!("> {}", format_age(survival.max_age)),
+    }
+}
+
+/// Print the number of lines written, their half-life and the number of removed lines for the
+/// whole repository and the top authors
+pub fn print_survival_report(all: &Survival, authors: &[(String, Survival)], top: usize) {
+    let mut rows = vec![(
+        "Author".to_string(),
+        "Lines".to_string(),
+        "Half-life".to_string(),
+        "Removed".to_string(),
+    )];
+
+    for (name, survival) in std::iter::once(("All", all))
+        .chain(authors.iter().take(top).map(|(name, s)| (name.as_str(), s)))
+    {
+        rows.push((
+            name.to_string(),
+            survival.lines.to_string(),
+            format_half_life(survival),
+            survival.removed.to_string(),
+        ));
+    }
+
+    print_table(rows);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_curve(actual: &[(i64, f64)], expected: &[(i64, f64)]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert_eq!(a.0, e.0);
+            assert!((a.1 - e.1).abs() < 1e-9, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn censored_lines_leave_the_risk_set() {
+        // 6 lines: 2 removed and 1 alive at 10, 1 removed at 20, 2 alive at 30. The survival
+        // drops to 1 - 2/6 at 10, then the 3 lines left at risk drop it by 1/3 at 20.
+        let survival = kaplan_meier(vec![
+            (30, 2, false),
+            (20, 1, true),
+            (10, 1, false),
+            (10, 2, true),
+        ]);
+        assert_curve(&survival.curve, &[(0, 1.0), (10, 2.0 / 3.0), (20, 4.0 / 9.0)]);
+        assert_eq!(survival.lines, 6);
+        assert_eq!(survival.removed, 3);
+        assert_eq!(survival.half_life, Some(20));
+        assert_eq!(survival.max_age, 30);
+    }
+
+    #[test]
+    fn half_life_is_reached_at_exactly_half() {
+        let survival = kaplan_meier(vec![(5, 1, true), (8, 1, false)]);
+        assert_curve(&survival.curve, &[(0, 1.0), (5, 0.5)]);
+        assert_eq!(survival.half_life, Some(5));
+    }
+
+    #[test]
+    fn all_censored_has_no_half_life() {
+        let survival = kaplan_meier(vec![(7, 3, false), (2, 1, false)]);
+        assert_curve(&survival.curve, &[(0, 1.0)]);
+        assert_eq!(survival.removed, 0);
+        assert_eq!(survival.half_life, None);
+        assert_eq!(survival.max_age, 7);
+    }
+}
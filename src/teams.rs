@@ -0,0 +1,151 @@
+use super::config::load_file;
+use super::plotting::AuthorInfo;
+use glob::{MatchOptions, Pattern};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TeamFile {
+    teams: BTreeMap<String, Vec<String>>,
+    unmatched: Option<String>,
+}
+
+/// The teams that the authors belong to, which is loaded from a TOML or YAML file like:
+///
+/// ```toml
+/// unmatched = "Community"
+///
+/// [teams]
+/// Core = ["Alice", "bob@example.com", "*@example.com"]
+/// Docs = ["Carol*"]
+/// ```
+///
+/// Each entry matches either the name or any email of an author. When an author matches
+/// multiple teams, an entry without any wildcard wins over the globs, otherwise the team that
+/// comes first in alphabetical order wins.
+pub struct Teams {
+    teams: Vec<(String, Vec<Pattern>)>,
+    unmatched: String,
+}
+
+fn is_literal(pattern: &Pattern) -> bool {
+    Pattern::escape(pattern.as_str()) == pattern.as_str()
+}
+
+impl Teams {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        load_file(path).map(Self::from_file)
+    }
+
+    fn from_file(file: TeamFile) -> Self {
+        // A member that isn't a valid glob is matched literally
+        let teams = file
+            .teams
+            .into_iter()
+            .map(|(team, members)| {
+                let patterns = members
+                    .iter()
+                    .map(|member| {
+                        Pattern::new(member)
+                            .unwrap_or_else(|_| Pattern::new(&Pattern::escape(member)).unwrap())
+                    })
+                    .collect();
+                (team, patterns)
+            })
+            .collect();
+
+        Self {
+            teams,
+            unmatched: file.unmatched.unwrap_or_else(|| "Unassigned".to_string()),
+        }
+    }
+
+    /// The team of the author, the emails are matched case-insensitively
+    pub fn team_of(&self, name: &str, emails: &[String]) -> &str {
+        let email_options = MatchOptions {
+            case_sensitive: false,
+            ..MatchOptions::new()
+        };
+        let matches = |pattern: &Pattern| {
+            pattern.matches(name)
+                || emails
+                    .iter()
+                    .any(|email| pattern.matches_with(email, email_options))
+        };
+        let find = |literal_only: bool| {
+            self.teams.iter().find(|(_, patterns)| {
+                patterns
+                    .iter()
+                    .any(|p| (!literal_only || is_literal(p)) && matches(p))
+            })
+        };
+        find(true)
+            .or_else(|| find(false))
+            .map_or(&self.unmatched, |(team, _)| team)
+    }
+
+    /// Sum up the lines owned by the members of each team. The pseudo author "Older Code" isn't
+    /// put into the unmatched team, so that it can still be excluded.
+    pub fn aggregate(
+        &self,
+        author_info: AuthorInfo,
+        emails: &HashMap<String, Vec<String>>,
+    ) -> AuthorInfo {
+        let mut ret: AuthorInfo = HashMap::new();
+        for (name, stats) in author_info {
+            let emails = emails.get(&name).map_or(&[][..], Vec::as_slice);
+            let team = match self.team_of(&name, emails) {
+                team if team == self.unmatched && name == "Older Code" => name,
+                team => team.to_string(),
+            };
+            let team_stats = ret.entry(team).or_default();
+            for (date, count) in stats {
+                *team_stats.entry(date).or_default() += count;
+            }
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn teams(content: &str) -> Teams {
+        Teams::from_file(toml::from_str(content).unwrap())
+    }
+
+    fn emails(emails: &[&str]) -> Vec<String> {
+        emails.iter().map(|email| email.to_string()).collect()
+    }
+
+    #[test]
+    fn unmatched_authors_are_unassigned() {
+        let teams = teams("[teams]\nCore = [\"Alice\", \"*@example.com\"]\n");
+        assert_eq!(teams.team_of("Alice", &[]), "Core");
+        assert_eq!(teams.team_of("Bob", &emails(&["bob@Example.COM"])), "Core");
+        assert_eq!(
+            teams.team_of("Bob", &emails(&["bob@other.org"])),
+            "Unassigned"
+        );
+        assert_eq!(teams.team_of("alice", &[]), "Unassigned");
+    }
+
+    #[test]
+    fn unmatched_team_is_configurable() {
+        let teams = teams("unmatched = \"Community\"\n[teams]\nCore = [\"Alice\"]\n");
+        assert_eq!(teams.team_of("Bob", &[]), "Community");
+    }
+
+    #[test]
+    fn literal_entries_win_over_globs() {
+        let teams =
+            teams("[teams]\nA = [\"*@example.com\"]\nB = [\"Bob\"]\nC = [\"Carol*\", \"Bob*\"]\n");
+        assert_eq!(teams.team_of("Bob", &emails(&["bob@example.com"])), "B");
+        // Among the globs, the team first in alphabetical order wins
+        assert_eq!(teams.team_of("Carol", &emails(&["carol@example.com"])), "A");
+        assert_eq!(teams.team_of("Carol", &[]), "C");
+    }
+}
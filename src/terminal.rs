@@ -0,0 +1,324 @@
+use super::options::ConstatOptions;
+use super::plotting::{
+    chart_title, final_ownership, name_column, repo_name, stack_series, AuthorInfo, AuthorStat,
+};
+use super::report::print_columns;
+use chrono::{DateTime, Datelike, Utc};
+use console::{colors_enabled, style, Color, Term};
+
+/// The 256-color codes of the series, which are close to the palette of the image charts
+const COLORS: &[u8] = &[
+    161, 71, 220, 32, 208, 91, 87, 201, 154, 217, 30, 183, 130, 230, 88, 158, 100, 223, 18, 244,
+];
+
+/// The fill of the series when the colors are disabled, for example when the output is piped
+const SYMBOLS: &[char] = &['█', '▓', '▒', '░', '#', '%', '=', '+', ':', '.'];
+
+/// The blocks filling 1/8 to 8/8 of a cell from the bottom
+const VERTICAL_BLOCKS: &[char] = &['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// The blocks filling 1/8 to 8/8 of a cell from the left
+const HORIZONTAL_BLOCKS: &[char] = &['▏', '▎', '▍', '▌', '▋', '▊', '▉', '█'];
+
+/// The size of the output when stdout isn't a terminal and `$COLUMNS` isn't set
+const DEFAULT_SIZE: (usize, usize) = (80, 16);
+
+const MIN_CHART_WIDTH: usize = 10;
+
+fn paint(text: String, series: usize) -> String {
+    style(text)
+        .fg(Color::Color256(COLORS[series % COLORS.len()]))
+        .to_string()
+}
+
+/// The width and the chart height that fit the terminal
+fn output_size() -> (usize, usize) {
+    match Term::stdout().size_checked() {
+        Some((rows, cols)) => (cols as usize, (rows as usize / 2).clamp(8, 24)),
+        None => std::env::var("COLUMNS")
+            .ok()
+            .and_then(|cols| cols.parse().ok())
+            .map_or(DEFAULT_SIZE, |cols| (cols, DEFAULT_SIZE.1)),
+    }
+}
+
+fn day<D: Datelike>(date: &D) -> i64 {
+    date.num_days_from_ce() as i64
+}
+
+/// The value of the series at the day, which is carried over from the last date before it
+fn value_at<D: Datelike>(stat: &[(D, usize)], at: i64) -> usize {
+    match stat.partition_point(|(date, _)| day(date) <= at) {
+        0 => 0,
+        idx => stat[idx - 1].1,
+    }
+}
+
+/// Draw the stacked chart with the blocks, each column of the chart is a sample of the time
+/// range and each cell is divided into eighths vertically
+fn print_chart(
+    series: &[AuthorStat],
+    markers: &[(String, DateTime<Utc>)],
+    percentage: bool,
+    (width, height): (usize, usize),
+) {
+    let days = || series.iter().flat_map(|(_, stat)| stat.iter().map(|(date, _)| day(date)));
+    let start = days().min().unwrap();
+    let end = days().max().unwrap();
+    let span = (end - start).max(1);
+
+    let max_total = days()
+        .map(|at| series.iter().map(|(_, stat)| value_at(stat, at)).sum::<usize>())
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let label_of = |eighths: usize| {
+        if percentage {
+            format!("{}%", eighths * 100 / (height * 8))
+        } else {
+            (eighths * max_total / (height * 8)).to_string()
+        }
+    };
+    let label_width = label_of(height * 8).len();
+    let cols = width.saturating_sub(label_width + 2).max(MIN_CHART_WIDTH);
+    let day_of = |col: usize| start + span * col as i64 / (cols - 1) as i64;
+    let col_of = |at: i64| ((at - start) * (cols - 1) as i64 / span) as usize;
+
+    // The top of each series in eighths of a cell, stacked from the first series
+    let tops: Vec<Vec<usize>> = (0..cols)
+        .map(|col| {
+            let values: Vec<_> = series
+                .iter()
+                .map(|(_, stat)| value_at(stat, day_of(col)))
+                .collect();
+            let total = if percentage {
+                values.iter().sum::<usize>().max(1)
+            } else {
+                max_total
+            };
+            let mut acc = 0;
+            values
+                .iter()
+                .map(|value| {
+                    acc += value;
+                    (acc as f64 * (height * 8) as f64 / total as f64).round() as usize
+                })
+                .collect()
+        })
+        .collect();
+
+    let colored = colors_enabled();
+    let cell = |col: usize, bottom: usize| -> String {
+        let covering = |at: usize| tops[col].iter().position(|&top| top > at);
+        if !colored {
+            return covering(bottom + 4).map_or(" ".to_string(), |idx| {
+                SYMBOLS[idx % SYMBOLS.len()].to_string()
+            });
+        }
+        let lower = match covering(bottom) {
+            Some(idx) => idx,
+            None => return " ".to_string(),
+        };
+        let filled = (tops[col][lower] - bottom).min(8);
+        let block =
+            style(VERTICAL_BLOCKS[filled - 1]).fg(Color::Color256(COLORS[lower % COLORS.len()]));
+        match covering(tops[col][lower]).filter(|_| filled < 8) {
+            Some(upper) => block.bg(Color::Color256(COLORS[upper % COLORS.len()])).to_string(),
+            None => block.to_string(),
+        }
+    };
+
+    for row in (0..height).rev() {
+        let labeled = row + 1 == height || row + 1 == height / 2;
+        let (label, axis) = if labeled {
+            (label_of((row + 1) * 8), '┤')
+        } else {
+            (String::new(), '│')
+        };
+        let line: String = (0..cols).map(|col| cell(col, row * 8)).collect();
+        println!("{:>w$} {}{}", label, axis, line, w = label_width);
+    }
+
+    // The x axis, the tags are marked on it and labeled below the dates
+    let markers: Vec<_> = markers
+        .iter()
+        .map(|(name, time)| (name, day(&time.date_naive())))
+        .filter(|(_, at)| (start..=end).contains(at))
+        .map(|(name, at)| (name, col_of(at)))
+        .collect();
+    let mut axis = vec!['─'; cols];
+    for (_, col) in markers.iter() {
+        axis[*col] = '┴';
+    }
+    println!(
+        "{:>w$} └{}",
+        label_of(0),
+        axis.into_iter().collect::<String>(),
+        w = label_width
+    );
+
+    let date_of = |at: i64| {
+        chrono::NaiveDate::from_num_days_from_ce_opt(at as i32)
+            .map_or_else(String::new, |date| date.format("%Y-%m-%d").to_string())
+    };
+    let (first, last) = (date_of(start), date_of(end));
+    println!(
+        "{:w$}  {}{:>rest$}",
+        "",
+        first,
+        last,
+        w = label_width,
+        rest = cols.saturating_sub(first.len())
+    );
+
+    // The names of the tags that don't overlap the ones before
+    let mut tags = String::new();
+    for (name, col) in markers {
+        if col >= tags.chars().count() + usize::from(!tags.is_empty()) {
+            tags.push_str(&" ".repeat(col - tags.chars().count()));
+            tags.push_str(name);
+        }
+    }
+    if !tags.is_empty() {
+        println!("{:w$}  {}", "", tags, w = label_width);
+    }
+}
+
+/// The legend of the series, wrapped to the width
+fn print_legend(series: &[AuthorStat], width: usize) {
+    let colored = colors_enabled();
+    let mut line = String::new();
+    let mut line_width = 0;
+    for (idx, (name, _)) in series.iter().enumerate() {
+        let entry_width = name.chars().count() + 3;
+        if line_width > 0 && line_width + entry_width + 2 > width {
+            println!("{}", line);
+            line.clear();
+            line_width = 0;
+        }
+        if line_width > 0 {
+            line.push_str("  ");
+            line_width += 2;
+        }
+        let swatch = if colored {
+            paint("██".to_string(), idx)
+        } else {
+            SYMBOLS[idx % SYMBOLS.len()].to_string().repeat(2)
+        };
+        line.push_str(&format!("{} {}", swatch, name));
+        line_width += entry_width;
+    }
+    println!("{}", line);
+}
+
+/// A bar of the share with the eighths of a cell
+fn bar(share: f64, width: usize) -> String {
+    let eighths = (share * (width * 8) as f64).round() as usize;
+    let mut bar = "█".repeat(eighths / 8);
+    if eighths % 8 != 0 {
+        bar.push(HORIZONTAL_BLOCKS[eighths % 8 - 1]);
+    }
+    bar
+}
+
+/// Print the top owners at the end with the bars of their shares, the rest are summed up in the
+/// Others row
+fn print_ownership(
+    owners: &[(String, usize)],
+    series: &[AuthorStat],
+    column: &str,
+    top: usize,
+    width: usize,
+) {
+    let total: usize = owners.iter().map(|(_, lines)| lines).sum();
+    let share = |lines: usize| lines as f64 / total.max(1) as f64;
+
+    let mut rows = vec![vec![
+        "#".to_string(),
+        column.to_string(),
+        "Lines".to_string(),
+        "Share".to_string(),
+        String::new(),
+    ]];
+    let mut row_of = |rank: String, name: &str, lines: usize| {
+        rows.push(vec![
+            rank,
+            name.to_string(),
+            lines.to_string(),
+            format!("{:.1}%", share(lines) * 100.0),
+        ]);
+    };
+    for (idx, (name, lines)) in owners.iter().take(top).enumerate() {
+        row_of((idx + 1).to_string(), name, *lines);
+    }
+    if owners.len() > top {
+        let others = owners[top..].iter().map(|(_, lines)| lines).sum();
+        row_of(String::new(), "Others", others);
+    }
+    row_of(String::new(), "Total", total);
+
+    // The bars take the rest of the width
+    let used: usize = (0..4)
+        .map(|idx| rows.iter().map(|row| row[idx].chars().count()).max().unwrap_or(0) + 2)
+        .sum();
+    let bar_width = width.saturating_sub(used + 1).max(MIN_CHART_WIDTH);
+    let colored = colors_enabled();
+    let last = rows.len() - 1;
+    for (idx, row) in rows.iter_mut().enumerate().skip(1) {
+        if idx == last {
+            row.push(String::new());
+            continue;
+        }
+        let lines = row[2].parse().unwrap_or(0);
+        let bar = bar(share(lines), bar_width);
+        match series.iter().position(|(name, _)| *name == row[1]) {
+            Some(series) if colored => row.push(paint(bar, series)),
+            _ => row.push(bar),
+        }
+    }
+
+    print_columns(&rows, &[true, false, true, true, false]);
+}
+
+/// Print the stacked chart and the ownership table to stdout, sized to the terminal
+pub fn print_term(
+    author_info: &mut AuthorInfo,
+    markers: &[(String, DateTime<Utc>)],
+    options: &ConstatOptions,
+) {
+    let (width, height) = output_size();
+    let owners = final_ownership(author_info, options);
+    let series = stack_series(author_info, options);
+
+    println!(
+        "{} for {}",
+        chart_title(options.chart, options.percentage),
+        repo_name(&options.repo_path)
+    );
+    println!();
+    if series.is_empty() {
+        println!("No lines found");
+        return;
+    }
+    print_chart(&series, markers, options.percentage, (width, height));
+    println!();
+    print_legend(&series, width);
+    println!();
+    print_ownership(&owners, &series, name_column(options), options.top, width);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bar_uses_the_eighths_of_a_cell() {
+        assert_eq!(bar(0.0, 4), "");
+        assert_eq!(bar(1.0, 4), "████");
+        assert_eq!(bar(0.5, 4), "██");
+        // 0.3 of 32 eighths rounds to 10, a full cell and a quarter
+        assert_eq!(bar(0.3, 4), "█▎");
+        assert_eq!(bar(1.0 / 32.0, 4), "▏");
+        assert_eq!(bar(0.01, 4), "");
+    }
+}